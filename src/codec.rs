@@ -0,0 +1,603 @@
+//! Compact binary snapshots of a [`SkipList`], available with the `std` feature.
+//!
+//! A snapshot is written with [`SkipList::write_snapshot`] and loaded with
+//! [`SkipList::read_snapshot`], which rebuilds the list in `O(n)`.
+//! Keys and values are encoded through the [`Codec`] trait.
+//!
+//! # Format
+//! All integers are little endian.
+//!
+//! | Field        | Size | Description                                          |
+//! |--------------|------|------------------------------------------------------|
+//! | magic        | 8    | `SUBWAYSL`                                           |
+//! | version      | 2    | format version, currently `1`                        |
+//! | flags        | 2    | bit `0` is set when entries carry their tower height |
+//! | entry count  | 8    | number of entries that follow                        |
+//! | header check | 4    | CRC-32 of the fields above                           |
+//! | entries      |      | key and value each prefixed by a 4 byte length, followed by a 1 byte tower height if flagged |
+//! | checksum     | 4    | CRC-32 of every preceding byte                       |
+//!
+//! Reading fails with [`io::ErrorKind::UnexpectedEof`] for a truncated snapshot
+//! and with [`io::ErrorKind::InvalidData`] for a corrupt one. The header checksum
+//! tells a corrupt entry count apart from a snapshot that was cut short.
+use crate::crc::Crc32;
+use crate::merge::Entry;
+use crate::skiplist::{SkipList, SkipListBuilder};
+use std::convert::TryFrom;
+use std::io::{self, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 8] = b"SUBWAYSL";
+const VERSION: u16 = 1;
+const FLAG_HEIGHTS: u16 = 1;
+// Upper bound on the capacity reserved from an untrusted entry count.
+const MAX_PREALLOCATED_ENTRIES: u64 = 4096;
+
+/// Binary encoding of keys and values stored in a snapshot.
+///
+/// Every encoded value is stored with its length, so implementations do not
+/// need to delimit their output.
+///
+/// # Example
+/// ```rust
+/// use std::io;
+/// use subway::codec::Codec;
+///
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// impl Codec for Point {
+///     fn encode(&self, buf: &mut Vec<u8>) {
+///         self.x.encode(buf);
+///         self.y.encode(buf);
+///     }
+///
+///     fn decode(bytes: &[u8]) -> io::Result<Point> {
+///         if bytes.len() != 8 {
+///             return Err(io::Error::new(io::ErrorKind::InvalidData, "bad point"));
+///         }
+///         Ok(Point {
+///             x: i32::decode(&bytes[..4])?,
+///             y: i32::decode(&bytes[4..])?,
+///         })
+///     }
+/// }
+/// ```
+pub trait Codec: Sized {
+    /// Append the encoded value to `buf`.
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Decode a value from the bytes written by [`encode`](#tymethod.encode).
+    fn decode(bytes: &[u8]) -> io::Result<Self>;
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+macro_rules! impl_codec_for_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl Codec for $ty {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> io::Result<$ty> {
+                    let mut array = [0u8; std::mem::size_of::<$ty>()];
+                    if bytes.len() != array.len() {
+                        return Err(invalid_data("integer has the wrong length"));
+                    }
+                    array.copy_from_slice(bytes);
+                    Ok(<$ty>::from_le_bytes(array))
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_for_integer!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// Sizes are stored as 64 bit integers so that snapshots are portable.
+impl Codec for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf);
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<usize> {
+        let value = u64::decode(bytes)?;
+        usize::try_from(value).map_err(|_| invalid_data("size does not fit in usize"))
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<bool> {
+        match bytes {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(invalid_data("invalid boolean")),
+        }
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<String> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid_data("string is not valid utf-8"))
+    }
+}
+
+impl Codec for Vec<u8> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self);
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+}
+
+// A leading byte tells `None` apart from a value that encodes to nothing.
+impl<T: Codec> Codec for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                buf.push(1);
+                value.encode(buf);
+            }
+            None => buf.push(0),
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Option<T>> {
+        match bytes.split_first() {
+            Some((0, [])) => Ok(None),
+            Some((1, value)) => T::decode(value).map(Some),
+            _ => Err(invalid_data("invalid option")),
+        }
+    }
+}
+
+// Encoded like `Option<V>`, with a tombstone in place of `None`.
+impl<V: Codec> Codec for Entry<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Entry::Value(value) => {
+                buf.push(1);
+                value.encode(buf);
+            }
+            Entry::Tombstone => buf.push(0),
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Entry<V>> {
+        match bytes.split_first() {
+            Some((0, [])) => Ok(Entry::Tombstone),
+            Some((1, value)) => V::decode(value).map(Entry::Value),
+            _ => Err(invalid_data("invalid entry")),
+        }
+    }
+}
+
+impl<K, V> SkipList<K, V>
+where
+    K: Ord + Clone + Codec,
+    V: Clone + Codec,
+{
+    /// Write the entries of the list as a binary snapshot.
+    /// See the [module documentation](../codec/index.html) for the format.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::SkipList;
+    ///
+    /// let mut list = SkipList::new();
+    /// list.insert(1u64, "one".to_owned());
+    /// list.insert(2u64, "two".to_owned());
+    /// let mut bytes = Vec::new();
+    /// list.write_snapshot(&mut bytes).unwrap();
+    ///
+    /// let restored: SkipList<u64, String> = SkipList::read_snapshot(&bytes[..]).unwrap();
+    /// assert_eq!(restored.collect(), list.collect());
+    /// ```
+    pub fn write_snapshot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_snapshot_inner(writer, false)
+    }
+
+    /// Write the entries of the list together with the height of every tower,
+    /// so that [`read_snapshot`](#method.read_snapshot) restores the exact shape of the list.
+    pub fn write_snapshot_with_heights<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_snapshot_inner(writer, true)
+    }
+
+    /// Read a list from a snapshot written by [`write_snapshot`](#method.write_snapshot).
+    /// Towers are given their stored heights when present and random heights otherwise.
+    ///
+    /// The reader is not read past the end of the snapshot, so the snapshot may be
+    /// followed by other data. Reads are not buffered, wrap a reader such as a
+    /// `File` in a `BufReader` first.
+    ///
+    /// # Returns
+    /// An error of kind [`io::ErrorKind::UnexpectedEof`] if the snapshot is truncated and
+    /// of kind [`io::ErrorKind::InvalidData`] if it is corrupt or from an unsupported version.
+    pub fn read_snapshot<R: Read>(reader: R) -> io::Result<SkipList<K, V>> {
+        SkipList::read_snapshot_with(reader, SkipListBuilder::new())
+    }
+
+    /// Read a list from a snapshot into a list configured by `builder`.
+    /// See [`read_snapshot`](#method.read_snapshot).
+    ///
+    /// # Returns
+    /// An error of kind [`io::ErrorKind::InvalidData`] also if the entries break the
+    /// capacity limit, uniqueness or memory limit of the builder.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::{SkipList, SkipListBuilder};
+    ///
+    /// let mut list = SkipList::new();
+    /// list.insert(1u64, 10u64);
+    /// let mut bytes = Vec::new();
+    /// list.write_snapshot(&mut bytes).unwrap();
+    ///
+    /// let builder = SkipListBuilder::new().probability(0.25).unique(true);
+    /// let mut restored: SkipList<u64, u64> =
+    ///     SkipList::read_snapshot_with(&bytes[..], builder).unwrap();
+    /// assert!(restored.try_insert(1, 11).is_err());
+    /// ```
+    pub fn read_snapshot_with<R: Read>(
+        reader: R,
+        builder: SkipListBuilder,
+    ) -> io::Result<SkipList<K, V>> {
+        let mut input = ChecksumReader::new(reader);
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a skip list snapshot"));
+        }
+        let version = u16::from_le_bytes(read_array(&mut input)?);
+        if version != VERSION {
+            return Err(invalid_data("unsupported snapshot version"));
+        }
+        let flags = u16::from_le_bytes(read_array(&mut input)?);
+        let count = u64::from_le_bytes(read_array(&mut input)?);
+        let expected = input.crc.finish();
+        if u32::from_le_bytes(read_array(&mut input)?) != expected {
+            return Err(invalid_data("snapshot header checksum mismatch"));
+        }
+        if flags & !FLAG_HEIGHTS != 0 {
+            return Err(invalid_data("unknown snapshot flags"));
+        }
+        let capacity = count.min(MAX_PREALLOCATED_ENTRIES) as usize;
+        let mut entries = Vec::with_capacity(capacity);
+        let mut heights = if flags & FLAG_HEIGHTS != 0 {
+            Some(Vec::with_capacity(capacity))
+        } else {
+            None
+        };
+        // keep reading after a value fails to decode, a checksum
+        // mismatch better explains what went wrong
+        let mut decode_error = None;
+        let mut field = Vec::new();
+        for _ in 0..count {
+            read_field(&mut input, &mut field)?;
+            let key = K::decode(&field);
+            read_field(&mut input, &mut field)?;
+            let value = V::decode(&field);
+            if let Some(heights) = heights.as_mut() {
+                let [height] = read_array(&mut input)?;
+                heights.push(height as usize);
+            }
+            match (key, value) {
+                (Ok(key), Ok(value)) => entries.push((key, value)),
+                (Err(error), _) | (_, Err(error)) => {
+                    decode_error.get_or_insert(error);
+                }
+            }
+        }
+        let expected = input.crc.finish();
+        let checksum = u32::from_le_bytes(read_array(&mut input.inner)?);
+        if checksum != expected {
+            return Err(invalid_data("snapshot checksum mismatch"));
+        }
+        if let Some(error) = decode_error {
+            return Err(error);
+        }
+        if !entries.windows(2).all(|pair| pair[0].0 <= pair[1].0) {
+            return Err(invalid_data("snapshot entries are not sorted"));
+        }
+        let mut list: SkipList<K, V> = builder.build();
+        if list.is_unique() && entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(invalid_data("snapshot holds duplicate keys"));
+        }
+        if list
+            .capacity_limit()
+            .is_some_and(|limit| entries.len() > limit)
+        {
+            return Err(invalid_data("snapshot exceeds the capacity limit"));
+        }
+        list.load_sorted(entries, heights);
+        if list
+            .memory_limit()
+            .is_some_and(|limit| list.memory_usage() > limit)
+        {
+            return Err(invalid_data("snapshot exceeds the memory limit"));
+        }
+        Ok(list)
+    }
+
+    fn write_snapshot_inner<W: Write>(&self, writer: &mut W, heights: bool) -> io::Result<()> {
+        let mut output = ChecksumWriter::new(BufWriter::new(writer));
+        output.write_all(MAGIC)?;
+        output.write_all(&VERSION.to_le_bytes())?;
+        let flags = if heights { FLAG_HEIGHTS } else { 0 };
+        output.write_all(&flags.to_le_bytes())?;
+        output.write_all(&(self.len() as u64).to_le_bytes())?;
+        let header_checksum = output.crc.finish();
+        output.write_all(&header_checksum.to_le_bytes())?;
+        let mut buf = Vec::new();
+        self.visit_entries(|key, value, height| {
+            buf.clear();
+            write_field(&mut buf, key)?;
+            write_field(&mut buf, value)?;
+            if heights {
+                buf.push(height.min(u8::MAX as usize) as u8);
+            }
+            output.write_all(&buf)
+        })?;
+        let checksum = output.crc.finish();
+        let mut inner = output.inner;
+        inner.write_all(&checksum.to_le_bytes())?;
+        inner.flush()
+    }
+}
+
+// Encode a value prefixed by its length.
+pub(crate) fn write_field<T: Codec>(buf: &mut Vec<u8>, value: &T) -> io::Result<()> {
+    let start = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    value.encode(buf);
+    let length = u32::try_from(buf.len() - start - 4).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "encoded value is longer than 4 GiB",
+        )
+    })?;
+    buf[start..start + 4].copy_from_slice(&length.to_le_bytes());
+    Ok(())
+}
+
+// Read a length prefixed value into `field`.
+// The buffer grows with the bytes actually read, so a corrupt length
+// does not reserve a huge allocation.
+pub(crate) fn read_field<R: Read>(input: &mut R, field: &mut Vec<u8>) -> io::Result<()> {
+    let length = u32::from_le_bytes(read_array(input)?) as u64;
+    field.clear();
+    input.by_ref().take(length).read_to_end(field)?;
+    if (field.len() as u64) < length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "snapshot is truncated",
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn read_array<R: Read, const N: usize>(input: &mut R) -> io::Result<[u8; N]> {
+    let mut array = [0u8; N];
+    input.read_exact(&mut array)?;
+    Ok(array)
+}
+
+// Reader that checksums the bytes passing through it.
+struct ChecksumReader<R> {
+    inner: R,
+    crc: Crc32,
+}
+
+impl<R> ChecksumReader<R> {
+    fn new(inner: R) -> ChecksumReader<R> {
+        ChecksumReader {
+            inner,
+            crc: Crc32::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.crc.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+// Writer that checksums the bytes passing through it.
+struct ChecksumWriter<W> {
+    inner: W,
+    crc: Crc32,
+}
+
+impl<W> ChecksumWriter<W> {
+    fn new(inner: W) -> ChecksumWriter<W> {
+        ChecksumWriter {
+            inner,
+            crc: Crc32::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skiplist::SkipListBuilder;
+
+    fn sample() -> SkipList<u64, String> {
+        let mut list = SkipListBuilder::new().seed(21).build();
+        for i in (0..1000).rev() {
+            list.insert(i, format!("value-{}", i));
+        }
+        list.insert(10, String::new());
+        list
+    }
+
+    fn snapshot(list: &SkipList<u64, String>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        list.write_snapshot(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let list = sample();
+        let bytes = snapshot(&list);
+        assert_eq!(&bytes[..8], MAGIC);
+        let mut restored: SkipList<u64, String> = SkipList::read_snapshot(&bytes[..]).unwrap();
+        assert_eq!(restored.collect(), list.collect());
+        assert_eq!(restored.get(&999), Some("value-999".to_owned()));
+        restored.insert(5000, "new".to_owned());
+        assert_eq!(restored.len(), 1002);
+        let empty: SkipList<u64, String> = SkipList::new();
+        let restored: SkipList<u64, String> =
+            SkipList::read_snapshot(&snapshot(&empty)[..]).unwrap();
+        assert!(restored.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip_with_heights() {
+        let list = sample();
+        let mut bytes = Vec::new();
+        list.write_snapshot_with_heights(&mut bytes).unwrap();
+        let restored: SkipList<u64, String> = SkipList::read_snapshot(&bytes[..]).unwrap();
+        assert_eq!(restored.collect(), list.collect());
+        assert_eq!(restored.stats(), list.stats());
+    }
+
+    #[test]
+    fn test_detects_truncation() {
+        let bytes = snapshot(&sample());
+        for length in [0, 7, 20, bytes.len() / 2, bytes.len() - 1] {
+            let result = SkipList::<u64, String>::read_snapshot(&bytes[..length]);
+            let error = result.err().expect("truncation was not detected");
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        let bytes = snapshot(&sample());
+        // flip a bit in the header, in an entry and in the checksum
+        for position in [9, 100, bytes.len() / 2, bytes.len() - 1] {
+            let mut corrupt = bytes.clone();
+            corrupt[position] ^= 0x10;
+            let result = SkipList::<u64, String>::read_snapshot(&corrupt[..]);
+            let error = result.err().expect("corruption was not detected");
+            // a corrupt length may also run past the end of the snapshot
+            assert!(matches!(
+                error.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ));
+        }
+        let mut other_format = bytes.clone();
+        other_format[0] = b'X';
+        let result = SkipList::<u64, String>::read_snapshot(&other_format[..]);
+        let error = result.err().expect("foreign file was accepted");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_corrupt_count_is_invalid_data() {
+        let mut bytes = snapshot(&sample());
+        // the entry count starts after the magic, version and flags
+        bytes[14] ^= 0x01;
+        let error = SkipList::<u64, String>::read_snapshot(&bytes[..])
+            .err()
+            .expect("snapshot was accepted");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_does_not_read_past_snapshot() {
+        let mut bytes = snapshot(&sample());
+        bytes.extend_from_slice(b"trailer");
+        let mut input = &bytes[..];
+        let restored: SkipList<u64, String> = SkipList::read_snapshot(&mut input).unwrap();
+        assert_eq!(restored.len(), 1001);
+        assert_eq!(input, b"trailer");
+    }
+
+    #[test]
+    fn test_read_snapshot_with_builder() {
+        let mut list = SkipList::new();
+        for i in 0..100u64 {
+            list.insert(i, i.to_string());
+        }
+        let bytes = snapshot(&list);
+        let builder = SkipListBuilder::new().probability(0.25).capacity_limit(100);
+        let restored: SkipList<u64, String> =
+            SkipList::read_snapshot_with(&bytes[..], builder).unwrap();
+        assert_eq!(restored.probability(), 0.25);
+        assert_eq!(restored.capacity_limit(), Some(100));
+        assert_eq!(restored.collect(), list.collect());
+        let builder = SkipListBuilder::new().capacity_limit(99);
+        let error = SkipList::<u64, String>::read_snapshot_with(&bytes[..], builder)
+            .err()
+            .expect("snapshot was accepted");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // the sample holds key 10 twice
+        let builder = SkipListBuilder::new().unique(true);
+        let duplicates = snapshot(&sample());
+        let error = SkipList::<u64, String>::read_snapshot_with(&duplicates[..], builder)
+            .err()
+            .expect("snapshot was accepted");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_codecs() {
+        let mut buf = Vec::new();
+        (-5i32).encode(&mut buf);
+        assert_eq!(i32::decode(&buf).unwrap(), -5);
+        assert!(u64::decode(&buf).is_err());
+        buf.clear();
+        usize::MAX.encode(&mut buf);
+        assert_eq!(buf.len(), 8);
+        assert!(bool::decode(&[2]).is_err());
+        assert!(String::decode(&[0xff, 0xfe]).is_err());
+        assert_eq!(Vec::<u8>::decode(&[1, 2]).unwrap(), vec![1, 2]);
+        buf.clear();
+        Some(String::new()).encode(&mut buf);
+        assert_eq!(Option::<String>::decode(&buf).unwrap(), Some(String::new()));
+        assert_eq!(Option::<String>::decode(&[0]).unwrap(), None);
+        assert!(Option::<String>::decode(&[0, 1]).is_err());
+        buf.clear();
+        Entry::Value(7u8).encode(&mut buf);
+        assert_eq!(Entry::<u8>::decode(&buf).unwrap(), Entry::Value(7));
+        // tables written with `Option` values read back as entries
+        let mut option = Vec::new();
+        Some(7u8).encode(&mut option);
+        assert_eq!(buf, option);
+        assert_eq!(Entry::<u8>::decode(&[0]).unwrap(), Entry::Tombstone);
+        assert!(Entry::<u8>::decode(&[2, 7]).is_err());
+    }
+}
@@ -0,0 +1,579 @@
+use crate::rng::{random_seed, tower_height, AtomicSplitMix64};
+use alloc::vec;
+use alloc::vec::Vec;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard};
+
+/// Probability with which a node is promoted to the next level.
+const PROBABILITY: f64 = 0.5;
+/// Upper bound on the number of levels.
+const MAX_LEVEL: usize = 32;
+
+type Link<K, V> = Option<Arc<Node<K, V>>>;
+
+struct Node<K, V> {
+    // `None` only for the head which precedes every node
+    key: Option<K>,
+    // taken by the thread that removes the node
+    value: RwLock<Option<V>>,
+    // successor of the node in each level it spans
+    next: Vec<RwLock<Link<K, V>>>,
+    // held while the successors of the node are being changed
+    lock: Mutex<()>,
+    // set once the node is logically removed, its successors never change afterwards
+    marked: AtomicBool,
+    // set once the node is linked into every level it spans
+    fully_linked: AtomicBool,
+}
+
+impl<K, V> Node<K, V> {
+    fn new(key: Option<K>, value: Option<V>, height: usize) -> Node<K, V> {
+        Node {
+            key,
+            value: RwLock::new(value),
+            next: (0..height).map(|_| RwLock::new(None)).collect(),
+            lock: Mutex::new(()),
+            marked: AtomicBool::new(false),
+            fully_linked: AtomicBool::new(false),
+        }
+    }
+
+    fn next(&self, level: usize) -> Link<K, V> {
+        read(&self.next[level]).clone()
+    }
+
+    fn set_next(&self, level: usize, link: Link<K, V>) {
+        *self.next[level]
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = link;
+    }
+
+    fn is_marked(&self) -> bool {
+        self.marked.load(Ordering::Acquire)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<K, V> Drop for Node<K, V> {
+    // Free the successors that nothing else refers to one at a time, dropping
+    // a long chain of `Arc`s recursively would overflow the stack. The chain
+    // may be the whole list or a run of removed nodes kept alive by an iterator.
+    fn drop(&mut self) {
+        let mut links: Vec<Arc<Node<K, V>>> = self.next.iter_mut().filter_map(take_link).collect();
+        while let Some(node) = links.pop() {
+            if let Ok(mut node) = Arc::try_unwrap(node) {
+                links.extend(node.next.iter_mut().filter_map(take_link));
+            }
+        }
+    }
+}
+
+fn take_link<K, V>(link: &mut RwLock<Link<K, V>>) -> Link<K, V> {
+    link.get_mut()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+}
+
+// Locks are never held across user code that can panic,
+// so a poisoned lock still guards consistent links.
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn same<K, V>(first: &Link<K, V>, second: &Link<K, V>) -> bool {
+    match (first, second) {
+        (Some(first), Some(second)) => Arc::ptr_eq(first, second),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// Lock each distinct predecessor once, starting from the bottom level.
+// Predecessors of higher levels never come after those of lower levels,
+// so every thread acquires node locks from the back of the list to the front.
+fn lock_all<K, V>(preds: &[Arc<Node<K, V>>]) -> Vec<MutexGuard<'_, ()>> {
+    let mut guards = Vec::with_capacity(preds.len());
+    for (level, pred) in preds.iter().enumerate() {
+        if level == 0 || !Arc::ptr_eq(pred, &preds[level - 1]) {
+            guards.push(pred.lock());
+        }
+    }
+    guards
+}
+
+/// Skip list that can be shared between threads, built on fine-grained locking.
+///
+/// The list is not lock-free. Lookups and range scans take a brief shared
+/// lock on every link they follow, so they may wait for a writer that is
+/// relinking that very node but never for updates elsewhere in the list.
+/// Updates lock only the nodes surrounding the key being changed and
+/// validate them before linking, so operations on different keys proceed
+/// in parallel.
+/// Removed nodes are reference counted and freed once the last thread
+/// still reading them moves on.
+///
+/// Unlike [`SkipList`](../skiplist/struct.SkipList.html) keys are unique,
+/// inserting an existing key replaces its value.
+///
+/// # Example
+/// ```rust
+/// use std::sync::Arc;
+/// use std::thread;
+/// use subway::concurrent::ConcurrentSkipList;
+///
+/// let list = Arc::new(ConcurrentSkipList::new());
+/// let workers: Vec<_> = (0..4)
+///     .map(|worker| {
+///         let list = Arc::clone(&list);
+///         thread::spawn(move || {
+///             for i in 0..100 {
+///                 list.insert(worker * 100 + i, i);
+///             }
+///         })
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap();
+/// }
+/// assert_eq!(list.len(), 400);
+/// assert_eq!(list.get(&250), Some(50));
+/// ```
+pub struct ConcurrentSkipList<K, V> {
+    head: Arc<Node<K, V>>,
+    // number of levels holding at least one node at some point
+    height: AtomicUsize,
+    size: AtomicUsize,
+    rng: AtomicSplitMix64,
+}
+
+impl<K, V> ConcurrentSkipList<K, V>
+where
+    K: Ord,
+{
+    /// Create an empty list.
+    pub fn new() -> ConcurrentSkipList<K, V> {
+        ConcurrentSkipList {
+            head: Arc::new(Node::new(None, None, MAX_LEVEL)),
+            height: AtomicUsize::new(1),
+            size: AtomicUsize::new(0),
+            rng: AtomicSplitMix64::new(random_seed()),
+        }
+    }
+
+    /// Insert the given key and value into the list.
+    ///
+    /// # Returns
+    /// The value previously associated with the key, if any.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::concurrent::ConcurrentSkipList;
+    ///
+    /// let list = ConcurrentSkipList::new();
+    /// assert_eq!(list.insert(1, "one"), None);
+    /// assert_eq!(list.insert(1, "uno"), Some("one"));
+    /// ```
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let height = tower_height(self.rng.next_u64(), PROBABILITY, MAX_LEVEL);
+        let mut value = Some(value);
+        loop {
+            let (preds, succs, found) = self.find(&key);
+            if let Some(level) = found {
+                let node = succs[level].as_ref().expect("found node is linked");
+                let _guard = node.lock();
+                if node.is_marked() {
+                    // the node is being removed, wait for it to be unlinked
+                    continue;
+                }
+                let mut current = node.value.write().unwrap_or_else(PoisonError::into_inner);
+                return current.replace(value.take().expect("value is inserted once"));
+            }
+            let guards = lock_all(&preds[..height]);
+            let valid = (0..height).all(|level| {
+                let succ = &succs[level];
+                !preds[level].is_marked()
+                    && succ.as_ref().is_none_or(|succ| !succ.is_marked())
+                    && same(&preds[level].next(level), succ)
+            });
+            if !valid {
+                continue;
+            }
+            self.height.fetch_max(height, Ordering::AcqRel);
+            let node = Arc::new(Node::new(Some(key), value.take(), height));
+            for (level, succ) in succs.into_iter().take(height).enumerate() {
+                node.set_next(level, succ);
+            }
+            for (level, pred) in preds.iter().take(height).enumerate() {
+                pred.set_next(level, Some(Arc::clone(&node)));
+            }
+            node.fully_linked.store(true, Ordering::Release);
+            drop(guards);
+            self.size.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+    }
+
+    /// Get the value associated with a key if it exists.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::concurrent::ConcurrentSkipList;
+    ///
+    /// let list = ConcurrentSkipList::new();
+    /// list.insert(20, "Strawberry Topping");
+    /// assert_eq!(list.get(&20), Some("Strawberry Topping"));
+    /// assert_eq!(list.get(&40), None);
+    /// ```
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let node = self.seek(Bound::Included(key))?;
+        if node.key.as_ref() != Some(key)
+            || node.is_marked()
+            || !node.fully_linked.load(Ordering::Acquire)
+        {
+            return None;
+        }
+        let value = read(&node.value);
+        value.clone()
+    }
+
+    /// Remove the key from the list.
+    ///
+    /// # Returns
+    /// The value associated with the key, if any.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::concurrent::ConcurrentSkipList;
+    ///
+    /// let list = ConcurrentSkipList::new();
+    /// list.insert(100, "Lemon Cream");
+    /// assert_eq!(list.remove(&100), Some("Lemon Cream"));
+    /// assert_eq!(list.remove(&100), None);
+    /// ```
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut victim: Link<K, V> = None;
+        loop {
+            let (preds, mut succs, found) = self.find(key);
+            let node = match &victim {
+                Some(node) => Arc::clone(node),
+                None => {
+                    let level = found?;
+                    let node = succs.swap_remove(level).expect("found node is linked");
+                    // a node that is still being linked has not been inserted yet
+                    if !node.fully_linked.load(Ordering::Acquire)
+                        || node.next.len() != level + 1
+                        || node.is_marked()
+                    {
+                        return None;
+                    }
+                    if !Self::mark(&node) {
+                        return None;
+                    }
+                    victim = Some(Arc::clone(&node));
+                    node
+                }
+            };
+            let height = node.next.len();
+            let guards = lock_all(&preds[..height]);
+            let valid = (0..height).all(|level| {
+                let pred = &preds[level];
+                !pred.is_marked() && same(&pred.next(level), &victim)
+            });
+            if !valid {
+                continue;
+            }
+            for level in (0..height).rev() {
+                preds[level].set_next(level, node.next(level));
+            }
+            drop(guards);
+            self.size.fetch_sub(1, Ordering::Relaxed);
+            let mut value = node.value.write().unwrap_or_else(PoisonError::into_inner);
+            return value.take();
+        }
+    }
+
+    /// Iterate over the entries whose keys fall in the given range.
+    ///
+    /// The iterator does not block writers. It reflects every update that
+    /// completed before it reached the affected key and may or may not
+    /// reflect updates made concurrently.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::concurrent::ConcurrentSkipList;
+    ///
+    /// let list = ConcurrentSkipList::new();
+    /// for i in 0..10 {
+    ///     list.insert(i, i * 10);
+    /// }
+    /// let entries: Vec<(i32, i32)> = list.range(3..6).collect();
+    /// assert_eq!(entries, vec![(3, 30), (4, 40), (5, 50)]);
+    /// ```
+    pub fn range<R>(&self, range: R) -> Range<K, V>
+    where
+        R: RangeBounds<K>,
+        K: Clone,
+    {
+        Range {
+            next: self.seek(range.start_bound()),
+            end: range.end_bound().cloned(),
+        }
+    }
+
+    /// Iterate over all entries in key order.
+    pub fn iter(&self) -> Range<K, V>
+    where
+        K: Clone,
+    {
+        self.range(..)
+    }
+
+    /// Returns the number of entries in the list.
+    pub fn len(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Logically remove the node, returns false if another thread got there first.
+    // Marking under the node lock waits for updates that validated the node as
+    // an unmarked predecessor, so its successors are final once it is marked.
+    fn mark(node: &Node<K, V>) -> bool {
+        let _guard = node.lock();
+        !node.marked.swap(true, Ordering::AcqRel)
+    }
+
+    // Predecessors and successors of the key in every level along with the
+    // highest level in which a node holding the key was found.
+    #[allow(clippy::type_complexity)]
+    fn find(&self, key: &K) -> (Vec<Arc<Node<K, V>>>, Vec<Link<K, V>>, Option<usize>) {
+        let mut preds = vec![Arc::clone(&self.head); MAX_LEVEL];
+        let mut succs = vec![None; MAX_LEVEL];
+        let mut found = None;
+        let mut pred = Arc::clone(&self.head);
+        for level in (0..self.height.load(Ordering::Acquire)).rev() {
+            let mut curr = pred.next(level);
+            while let Some(node) = curr.as_ref().filter(|node| node.key.as_ref() < Some(key)) {
+                pred = Arc::clone(node);
+                curr = pred.next(level);
+            }
+            if found.is_none() && curr.as_ref().and_then(|node| node.key.as_ref()) == Some(key) {
+                found = Some(level);
+            }
+            preds[level] = Arc::clone(&pred);
+            succs[level] = curr;
+        }
+        (preds, succs, found)
+    }
+
+    // First node in the bottom level whose key is not before the bound.
+    fn seek(&self, bound: Bound<&K>) -> Link<K, V> {
+        let before = |node: &Arc<Node<K, V>>| match (node.key.as_ref(), bound) {
+            (Some(key), Bound::Included(bound)) => key < bound,
+            (Some(key), Bound::Excluded(bound)) => key <= bound,
+            _ => false,
+        };
+        let mut pred = Arc::clone(&self.head);
+        let mut curr = None;
+        for level in (0..self.height.load(Ordering::Acquire)).rev() {
+            curr = pred.next(level);
+            while let Some(node) = curr.as_ref().filter(|node| before(node)) {
+                pred = Arc::clone(node);
+                curr = pred.next(level);
+            }
+        }
+        curr
+    }
+}
+
+impl<K, V> Default for ConcurrentSkipList<K, V>
+where
+    K: Ord,
+{
+    fn default() -> ConcurrentSkipList<K, V> {
+        ConcurrentSkipList::new()
+    }
+}
+
+/// Iterator over the entries of a [`ConcurrentSkipList`] within a range.
+pub struct Range<K, V> {
+    next: Link<K, V>,
+    end: Bound<K>,
+}
+
+impl<K, V> Iterator for Range<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.next.take() {
+            let key = node.key.as_ref()?;
+            let within = match &self.end {
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+                Bound::Unbounded => true,
+            };
+            if !within {
+                return None;
+            }
+            self.next = node.next(0);
+            if node.is_marked() || !node.fully_linked.load(Ordering::Acquire) {
+                continue;
+            }
+            if let Some(value) = read(&node.value).clone() {
+                return Some((key.clone(), value));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_send_sync;
+    use std::thread;
+
+    #[test]
+    fn test_is_send_and_sync() {
+        assert_send_sync::<ConcurrentSkipList<u64, String>>();
+        assert_send_sync::<Range<u64, String>>();
+    }
+
+    #[test]
+    fn test_operations() {
+        let list = ConcurrentSkipList::new();
+        assert!(list.is_empty());
+        for i in (0..100).rev() {
+            assert_eq!(list.insert(i, i * 2), None);
+        }
+        assert_eq!(list.len(), 100);
+        assert_eq!(list.insert(10, 0), Some(20));
+        assert_eq!(list.get(&10), Some(0));
+        assert_eq!(list.remove(&10), Some(0));
+        assert_eq!(list.get(&10), None);
+        assert_eq!(list.remove(&10), None);
+        assert_eq!(list.len(), 99);
+        let keys: Vec<i32> = list.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, (0..100).filter(|key| *key != 10).collect::<Vec<_>>());
+        let tail: Vec<i32> = list
+            .range((Bound::Excluded(97), Bound::Unbounded))
+            .map(|entry| entry.0)
+            .collect();
+        assert_eq!(tail, vec![98, 99]);
+        assert_eq!(list.range(..=2).count(), 3);
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        let list = ConcurrentSkipList::new();
+        thread::scope(|scope| {
+            for worker in 0..8 {
+                let list = &list;
+                scope.spawn(move || {
+                    for i in 0..1000 {
+                        // interleave keys so that workers contend on neighbouring nodes
+                        list.insert(i * 8 + worker, worker);
+                    }
+                });
+            }
+        });
+        assert_eq!(list.len(), 8000);
+        let keys: Vec<usize> = list.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, (0..8000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_concurrent_updates() {
+        let list = ConcurrentSkipList::new();
+        for i in 0..2000 {
+            list.insert(i, i);
+        }
+        thread::scope(|scope| {
+            for worker in 0..4 {
+                let list = &list;
+                scope.spawn(move || {
+                    for i in (worker..2000).step_by(4) {
+                        if i % 2 == 0 {
+                            assert_eq!(list.remove(&i), Some(i));
+                        } else {
+                            list.insert(i, i + 1);
+                        }
+                    }
+                });
+            }
+            let list = &list;
+            scope.spawn(move || {
+                for _ in 0..10 {
+                    let keys: Vec<usize> = list.iter().map(|(key, _)| key).collect();
+                    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+                }
+            });
+        });
+        assert_eq!(list.len(), 1000);
+        for i in 0..2000 {
+            let expected = if i % 2 == 0 { None } else { Some(i + 1) };
+            assert_eq!(list.get(&i), expected);
+        }
+    }
+
+    #[test]
+    fn test_contended_key() {
+        let list = ConcurrentSkipList::new();
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                let list = &list;
+                scope.spawn(move || {
+                    for i in 0..1000 {
+                        list.insert(1, i);
+                        list.remove(&1);
+                    }
+                });
+            }
+        });
+        assert!(list.is_empty());
+        assert_eq!(list.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_iterator_outlives_removed_run() {
+        let list = ConcurrentSkipList::new();
+        for i in 0..200_001 {
+            list.insert(i, i);
+        }
+        // the iterator keeps the first node, whose removed successors still
+        // link to each other
+        let held = list.iter();
+        let mut walking = list.iter();
+        for i in 0..200_000 {
+            assert_eq!(list.remove(&i), Some(i));
+        }
+        assert_eq!(walking.next(), Some((200_000, 200_000)));
+        assert_eq!(walking.next(), None);
+        // the held iterator is the last to refer to the removed run
+        drop(held);
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn test_drop_long_list() {
+        let list = ConcurrentSkipList::new();
+        for i in 0..200_000 {
+            list.insert(i, i);
+        }
+        drop(list);
+    }
+}
@@ -0,0 +1,559 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+// Marks the end of a level.
+const NIL: usize = usize::MAX;
+// Slot of the header tower which precedes every entry and spans every level.
+const HEAD: usize = 0;
+// Largest number of nodes allowed between two consecutive nodes of the level above.
+const MAX_GAP: usize = 3;
+
+struct Tower<K, V> {
+    // `None` for the header and for free slots
+    entry: Option<(K, V)>,
+    // successor of the tower in each level it spans
+    next: Vec<usize>,
+}
+
+/// Deterministic 1-2-3 skip list as described by Munro, Papadakis and Sedgewick.
+///
+/// Instead of flipping coins, nodes are promoted and demoted on insert and
+/// delete so that between any two consecutive nodes of a level there are
+/// always between one and three nodes of the level below.
+/// This bounds the height of the list by `log2(n + 1)` and makes search,
+/// insertion and deletion `O(log n)` in the worst case rather than in expectation.
+///
+/// It offers the same operations as [`SkipList`](../skiplist/struct.SkipList.html).
+///
+/// # Example
+/// ```rust
+/// use subway::deterministic::DeterministicSkipList;
+///
+/// let mut list = DeterministicSkipList::new();
+/// list.insert(2, "two");
+/// list.insert(1, "one");
+/// assert_eq!(list.get(&1), Some("one"));
+/// list.delete(&1);
+/// assert_eq!(list.collect(), vec![(2, "two")]);
+/// ```
+pub struct DeterministicSkipList<K, V> {
+    size: usize,
+    towers: Vec<Tower<K, V>>,
+    // slots of deleted towers available for reuse
+    free: Vec<usize>,
+}
+
+impl<K, V> DeterministicSkipList<K, V>
+where
+    K: Ord,
+{
+    /// Create an empty deterministic skip list.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::deterministic::DeterministicSkipList;
+    ///
+    /// let list: DeterministicSkipList<i32, i32> = DeterministicSkipList::new();
+    /// assert!(list.is_empty());
+    /// ```
+    pub fn new() -> DeterministicSkipList<K, V> {
+        DeterministicSkipList {
+            size: 0,
+            towers: vec![Tower {
+                entry: None,
+                next: Vec::new(),
+            }],
+            free: Vec::new(),
+        }
+    }
+
+    /// Insert the given key and value into the list.
+    /// Entries with equal keys are kept in insertion order.
+    ///
+    /// # Arguments
+    /// * _key_ - The key by which the value is to be accessed.
+    ///   This is also used as the sort key.
+    /// * _value_ - The value to store associated with the key.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::deterministic::DeterministicSkipList;
+    ///
+    /// let mut cakes = DeterministicSkipList::new();
+    /// cakes.insert(20, "Strawberry Topping".to_owned());
+    /// cakes.insert(40, "Chocolate Glaze".to_owned());
+    /// assert_eq!(cakes.len(), 2);
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.height() == 0 {
+            self.towers[HEAD].next.push(NIL);
+        }
+        let path = self.search_path(&key, NIL);
+        let tower = self.allocate(key, value);
+        let after = self.next(path[0], 0);
+        self.towers[tower].next.push(after);
+        self.towers[path[0]].next[0] = tower;
+        self.size += 1;
+        // split gaps that grew beyond the limit bottom up
+        let mut level = 0;
+        loop {
+            let (start, end) = self.gap_bounds(&path, level);
+            let gap = self.gap(start, end, level);
+            if gap.len() <= MAX_GAP {
+                break;
+            }
+            if level + 1 == self.height() {
+                self.towers[HEAD].next.push(NIL);
+            }
+            // promote the middle node into the level above
+            let promoted = gap[gap.len() / 2];
+            let after = self.next(start, level + 1);
+            self.towers[promoted].next.push(after);
+            self.towers[start].next[level + 1] = promoted;
+            level += 1;
+        }
+    }
+
+    /// Get the value associated with a key if it exists.
+    /// If the key was inserted more than once the most recent value is returned.
+    ///
+    /// # Arguments
+    /// * _key_ - The key whose value is to be read.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::deterministic::DeterministicSkipList;
+    ///
+    /// let mut cakes = DeterministicSkipList::new();
+    /// cakes.insert(20, "Strawberry Topping".to_owned());
+    /// assert_eq!(cakes.get(&20), Some("Strawberry Topping".to_owned()));
+    /// assert_eq!(cakes.get(&40), None);
+    /// ```
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let tower = self.find(key)?;
+        self.towers[tower]
+            .entry
+            .as_ref()
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Delete the value associated with the key.
+    /// If the key was inserted more than once the most recent entry is removed.
+    ///
+    /// # Arguments
+    /// * _key_ - The key associated with the value to delete.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::deterministic::DeterministicSkipList;
+    ///
+    /// let mut cakes = DeterministicSkipList::new();
+    /// cakes.insert(20, "Strawberry Topping".to_owned());
+    /// cakes.insert(100, "Lemon Cream".to_owned());
+    /// cakes.delete(&100);
+    /// assert_eq!(cakes.len(), 1);
+    /// ```
+    pub fn delete(&mut self, key: &K) {
+        let target = match self.find(key) {
+            Some(target) => target,
+            None => return,
+        };
+        let mut path = self.search_path(key, target);
+        let mut victim = target;
+        if self.towers[target].next.len() > 1 {
+            // the node before a tall tower always spans a single level,
+            // move its entry into the tall tower and remove it instead
+            let pred = path[0];
+            self.towers[target].entry = self.towers[pred].entry.take();
+            victim = pred;
+            let mut node = path[1];
+            while self.next(node, 0) != pred {
+                node = self.next(node, 0);
+            }
+            path[0] = node;
+        }
+        let after = self.next(victim, 0);
+        self.towers[path[0]].next[0] = after;
+        self.release(victim);
+        self.size -= 1;
+        // refill gaps emptied by the removal bottom up
+        let mut level = 0;
+        loop {
+            if level + 1 >= self.height() {
+                if self.next(HEAD, level) == NIL {
+                    self.towers[HEAD].next.pop();
+                }
+                break;
+            }
+            let (start, end) = self.gap_bounds(&path, level);
+            if !self.gap(start, end, level).is_empty() {
+                break;
+            }
+            if end != NIL && self.towers[end].next.len() == level + 2 {
+                // the gap after `end` shares the parent gap
+                let far = self.next(end, level + 1);
+                let sibling = self.gap(end, far, level);
+                self.towers[end].next.pop();
+                if sibling.len() > 1 {
+                    // borrow the first node of the sibling
+                    self.towers[sibling[0]].next.push(far);
+                    self.towers[start].next[level + 1] = sibling[0];
+                    break;
+                }
+                // merge with the sibling by demoting `end`
+                self.towers[start].next[level + 1] = far;
+            } else {
+                // `start` separates this gap from the one before it
+                let mut left = path.get(level + 2).copied().unwrap_or(HEAD);
+                while self.next(left, level + 1) != start {
+                    left = self.next(left, level + 1);
+                }
+                let sibling = self.gap(left, start, level);
+                self.towers[start].next.pop();
+                if sibling.len() > 1 {
+                    // borrow the last node of the sibling
+                    let last = sibling[sibling.len() - 1];
+                    self.towers[last].next.push(end);
+                    self.towers[left].next[level + 1] = last;
+                    break;
+                }
+                // merge with the sibling by demoting `start`
+                self.towers[left].next[level + 1] = end;
+            }
+            level += 1;
+        }
+    }
+
+    /// Find the largest key after which the supplied key can be inserted.
+    ///
+    /// # Arguments
+    /// * _key_ - The key whose insertion point is to be found.
+    ///
+    /// # Returns
+    /// `Option` of key after which the given key can be inserted.
+    /// `None` implies no key less that supplied key exists.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::deterministic::DeterministicSkipList;
+    ///
+    /// let mut list = DeterministicSkipList::new();
+    /// list.insert(1, "small");
+    /// list.insert(4, "x-large");
+    /// list.insert(3, "large");
+    /// assert_eq!(list.bisect(&2), Some(1));
+    /// assert_eq!(list.bisect(&0), None);
+    /// ```
+    pub fn bisect(&self, key: &K) -> Option<K>
+    where
+        K: Clone,
+    {
+        let tower = self.search_path(key, NIL)[0];
+        self.towers[tower]
+            .entry
+            .as_ref()
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Collect the entries sorted by key into a collection.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::deterministic::DeterministicSkipList;
+    ///
+    /// let mut list = DeterministicSkipList::new();
+    /// list.insert(2, 2);
+    /// list.insert(1, 1);
+    /// assert_eq!(list.collect(), vec![(1, 1), (2, 2)]);
+    /// ```
+    pub fn collect(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut values = Vec::with_capacity(self.size);
+        let mut tower = self.next(HEAD, 0);
+        while tower != NIL {
+            if let Some((key, value)) = self.towers[tower].entry.as_ref() {
+                values.push((key.clone(), value.clone()));
+            }
+            tower = self.next(tower, 0);
+        }
+        values
+    }
+
+    /// Returns the size of the list.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Returns whether list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns the number of levels in the list.
+    /// This never exceeds `log2(len + 1)`.
+    pub fn levels(&self) -> usize {
+        self.height()
+    }
+
+    /// Remove all entries from the list.
+    pub fn clear(&mut self) {
+        self.towers.truncate(1);
+        self.towers[HEAD].next.clear();
+        self.free.clear();
+        self.size = 0;
+    }
+
+    fn height(&self) -> usize {
+        self.towers[HEAD].next.len()
+    }
+
+    fn next(&self, tower: usize, level: usize) -> usize {
+        if tower == NIL {
+            return NIL;
+        }
+        self.towers[tower].next.get(level).copied().unwrap_or(NIL)
+    }
+
+    // Whether the tower holds a key that is not greater than the supplied key.
+    fn precedes(&self, tower: usize, key: &K) -> bool {
+        match self
+            .towers
+            .get(tower)
+            .and_then(|tower| tower.entry.as_ref())
+        {
+            Some((tower_key, _)) => tower_key.cmp(key) != Ordering::Greater,
+            None => false,
+        }
+    }
+
+    // Last tower holding the key.
+    fn find(&self, key: &K) -> Option<usize> {
+        let tower = self.search_path(key, NIL)[0];
+        match self.towers[tower].entry.as_ref() {
+            Some((tower_key, _)) if tower_key == key => Some(tower),
+            _ => None,
+        }
+    }
+
+    // The last tower in every level that holds a key not greater than the
+    // supplied key, stopping before `stop` so that the path to an existing
+    // tower lists its predecessors.
+    fn search_path(&self, key: &K, stop: usize) -> Vec<usize> {
+        let height = self.height();
+        let mut path = vec![HEAD; height.max(1)];
+        let mut tower = HEAD;
+        for level in (0..height).rev() {
+            loop {
+                let next = self.next(tower, level);
+                if next == stop || !self.precedes(next, key) {
+                    break;
+                }
+                tower = next;
+            }
+            path[level] = tower;
+        }
+        path
+    }
+
+    // Consecutive towers of the level above that enclose the path at this level.
+    fn gap_bounds(&self, path: &[usize], level: usize) -> (usize, usize) {
+        if level + 1 >= self.height() {
+            return (HEAD, NIL);
+        }
+        let start = path.get(level + 1).copied().unwrap_or(HEAD);
+        (start, self.next(start, level + 1))
+    }
+
+    // Towers strictly between `start` and `end` in the given level.
+    fn gap(&self, start: usize, end: usize, level: usize) -> Vec<usize> {
+        let mut gap = Vec::with_capacity(MAX_GAP + 1);
+        let mut tower = self.next(start, level);
+        while tower != end {
+            gap.push(tower);
+            tower = self.next(tower, level);
+        }
+        gap
+    }
+
+    fn allocate(&mut self, key: K, value: V) -> usize {
+        let tower = Tower {
+            entry: Some((key, value)),
+            next: Vec::new(),
+        };
+        match self.free.pop() {
+            Some(slot) => {
+                self.towers[slot] = tower;
+                slot
+            }
+            None => {
+                self.towers.push(tower);
+                self.towers.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, slot: usize) {
+        self.towers[slot].entry = None;
+        self.towers[slot].next.clear();
+        self.free.push(slot);
+    }
+}
+
+impl<K, V> Default for DeterministicSkipList<K, V>
+where
+    K: Ord,
+{
+    fn default() -> DeterministicSkipList<K, V> {
+        DeterministicSkipList::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SplitMix64;
+    use rand::RngCore;
+
+    // Check ordering, tower contiguity and the 1-2-3 gap invariant.
+    fn assert_valid(list: &DeterministicSkipList<i32, usize>) {
+        let height = list.height();
+        let entries = list.collect();
+        assert_eq!(entries.len(), list.len());
+        assert!(entries.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        for level in 0..height {
+            // every tower of a level is also present in the level below
+            let mut tower = list.next(HEAD, level);
+            while tower != NIL {
+                assert!(list.towers[tower].next.len() > level);
+                tower = list.next(tower, level);
+            }
+            let bounds: Vec<usize> = if level + 1 == height {
+                vec![HEAD, NIL]
+            } else {
+                let mut bounds = vec![HEAD];
+                let mut tower = list.next(HEAD, level + 1);
+                while tower != NIL {
+                    bounds.push(tower);
+                    tower = list.next(tower, level + 1);
+                }
+                bounds.push(NIL);
+                bounds
+            };
+            for pair in bounds.windows(2) {
+                let gap = list.gap(pair[0], pair[1], level);
+                assert!(!gap.is_empty() && gap.len() <= MAX_GAP);
+            }
+        }
+        if list.is_empty() {
+            assert_eq!(height, 0);
+        } else {
+            let bound = ((list.len() + 1) as f64).log2().ceil() as usize;
+            assert!(height <= bound.max(1));
+        }
+    }
+
+    #[test]
+    fn test_insert_sequential() {
+        let mut list = DeterministicSkipList::new();
+        for i in 0..1000 {
+            list.insert(i, i as usize);
+            assert_valid(&list);
+        }
+        assert_eq!(list.len(), 1000);
+        assert_eq!(list.get(&500), Some(500));
+        assert_eq!(list.get(&1000), None);
+    }
+
+    #[test]
+    fn test_insert_reverse() {
+        let mut list = DeterministicSkipList::new();
+        for i in (0..1000).rev() {
+            list.insert(i, i as usize);
+        }
+        assert_valid(&list);
+        assert_eq!(
+            list.collect(),
+            (0..1000).map(|i| (i, i as usize)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_delete_all() {
+        let mut list = DeterministicSkipList::new();
+        for i in 0..500 {
+            list.insert(i, i as usize);
+        }
+        for i in (0..500)
+            .filter(|i| i % 2 == 0)
+            .chain((0..500).filter(|i| i % 2 == 1))
+        {
+            list.delete(&i);
+            assert_valid(&list);
+            assert_eq!(list.get(&i), None);
+        }
+        assert!(list.is_empty());
+        assert_eq!(list.levels(), 0);
+        list.insert(1, 1);
+        assert_eq!(list.get(&1), Some(1));
+    }
+
+    #[test]
+    fn test_matches_model() {
+        let mut rng = SplitMix64::new(33);
+        let mut list = DeterministicSkipList::new();
+        let mut model: Vec<(i32, usize)> = Vec::new();
+        for step in 0..4000 {
+            let key = (rng.next_u64() % 200) as i32;
+            if rng.next_u64() % 5 < 2 {
+                list.delete(&key);
+                if let Some(position) = model.iter().rposition(|entry| entry.0 == key) {
+                    model.remove(position);
+                }
+            } else {
+                list.insert(key, step);
+                let position = model.iter().position(|entry| entry.0 > key);
+                model.insert(position.unwrap_or(model.len()), (key, step));
+            }
+            assert_valid(&list);
+            let expected = model
+                .iter()
+                .rev()
+                .find(|entry| entry.0 == key)
+                .map(|entry| entry.1);
+            assert_eq!(list.get(&key), expected);
+        }
+        assert_eq!(list.collect(), model);
+    }
+
+    #[test]
+    fn test_bisect() {
+        let mut list = DeterministicSkipList::new();
+        list.insert(4, 4);
+        list.insert(1, 1);
+        list.insert(2, 2);
+        list.insert(3, 2);
+        assert_eq!(list.bisect(&3), Some(3));
+        assert_eq!(list.bisect(&6), Some(4));
+        assert_eq!(list.bisect(&0), None);
+    }
+
+    #[test]
+    fn test_clear_reuses_list() {
+        let mut list = DeterministicSkipList::new();
+        for i in 0..100 {
+            list.insert(i, i);
+        }
+        list.clear();
+        assert!(list.is_empty());
+        assert_eq!(list.levels(), 0);
+        list.insert(5, 5);
+        assert_eq!(list.collect(), vec![(5, 5)]);
+    }
+}
@@ -0,0 +1,652 @@
+use crate::codec::{invalid_data, read_array, read_field, write_field, Codec};
+use crate::crc::crc32;
+use crate::skiplist::SkipList;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"SUBWAYWL";
+const VERSION: u16 = 1;
+const HEADER_LEN: u64 = 10;
+// Length, checksum of the length and checksum of the payload in front of every record.
+const RECORD_HEADER_LEN: u64 = 12;
+const OP_INSERT: u8 = 1;
+const OP_DELETE: u8 = 2;
+
+/// When a [`DurableSkipList`] or an [`LsmTree`](crate::lsm::LsmTree) forces its
+/// log to stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Sync after every change. A change that returned is never lost.
+    Always,
+    /// Sync after every `n` changes. Up to `n - 1` changes may be lost in a crash.
+    Batch(usize),
+    /// Leave syncing to the operating system. Changes survive a crash of the
+    /// process but not of the machine.
+    Never,
+}
+
+/// [`SkipList`] whose changes are recorded in a write-ahead log so that the
+/// list survives crashes and restarts.
+///
+/// Every `insert` and `delete` is appended to the log before it is applied.
+/// Opening a list replays the log, discarding a record left incomplete by a
+/// crash. [`checkpoint`](#method.checkpoint) writes a snapshot of the list
+/// next to the log and empties the log, so that it does not grow without bound.
+///
+/// Log records are checksummed and numbered. The snapshot remembers the
+/// number of the last record it includes, so a crash during a checkpoint
+/// never applies a change twice.
+///
+/// # Example
+/// ```rust
+/// use subway::durable::{DurableSkipList, SyncPolicy};
+///
+/// let path = std::env::temp_dir().join(format!("subway-doc-{}.log", std::process::id()));
+/// # let _ = std::fs::remove_file(&path);
+/// # let _ = std::fs::remove_file(path.with_extension("log.snapshot"));
+/// {
+///     let mut list = DurableSkipList::open(&path, SyncPolicy::Always)?;
+///     list.insert(1u64, "one".to_owned())?;
+///     list.insert(2u64, "two".to_owned())?;
+///     list.delete(&1)?;
+/// }
+/// let mut list: DurableSkipList<u64, String> = DurableSkipList::open(&path, SyncPolicy::Always)?;
+/// assert_eq!(list.get(&2), Some("two".to_owned()));
+/// assert_eq!(list.len(), 1);
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct DurableSkipList<K, V> {
+    list: SkipList<K, V>,
+    path: PathBuf,
+    log: Log<K, V>,
+}
+
+impl<K, V> DurableSkipList<K, V>
+where
+    K: Ord + Clone + Codec,
+    V: Clone + Codec,
+{
+    /// Open the list logged at `path`, creating an empty log if none exists.
+    ///
+    /// The snapshot written by the last [`checkpoint`](#method.checkpoint) is
+    /// loaded from `path` with `.snapshot` appended and the changes logged
+    /// since are replayed on top of it.
+    ///
+    /// # Returns
+    /// An error of kind [`io::ErrorKind::InvalidData`] if the log or the snapshot
+    /// is corrupt, or if `path` holds a file that is not a log. An incomplete record at the end of the log is not an error;
+    /// it is removed from the log. Damage that a crash while appending cannot
+    /// explain is an error, even in the last record.
+    pub fn open<P: AsRef<Path>>(path: P, sync: SyncPolicy) -> io::Result<DurableSkipList<K, V>> {
+        let path = path.as_ref().to_path_buf();
+        let (mut list, base) = match File::open(snapshot_path(&path)) {
+            Ok(file) => read_checkpoint(file)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (SkipList::new(), 0),
+            Err(error) => return Err(error),
+        };
+        let log = Log::open(&path, sync, base, |change| match change {
+            Change::Insert(key, value) => list.insert(key, value),
+            Change::Delete(key) => list.delete(&key),
+        })?;
+        Ok(DurableSkipList { list, path, log })
+    }
+
+    /// Insert the given key and value into the list once the change is logged.
+    pub fn insert(&mut self, key: K, value: V) -> io::Result<()> {
+        self.log.insert(&key, &value)?;
+        self.list.insert(key, value);
+        Ok(())
+    }
+
+    /// Delete the value associated with the key once the change is logged.
+    /// Keys that are not present are not logged.
+    pub fn delete(&mut self, key: &K) -> io::Result<()> {
+        if self.list.get(key).is_none() {
+            return Ok(());
+        }
+        self.log.delete(key)?;
+        self.list.delete(key);
+        Ok(())
+    }
+
+    /// Get the value associated with a key if it exists.
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.list.get(key)
+    }
+
+    /// Collects the entries of the list sorted by key.
+    pub fn collect(&self) -> Vec<(K, V)> {
+        self.list.collect()
+    }
+
+    /// Returns the number of entries in the list.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Returns whether list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Force every logged change to stable storage regardless of the sync policy.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.log.sync()
+    }
+
+    /// Write a snapshot of the list next to the log and empty the log.
+    ///
+    /// The snapshot replaces the previous one atomically, so a crash during a
+    /// checkpoint leaves either the old or the new snapshot in place.
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        let snapshot = snapshot_path(&self.path);
+        let mut temporary = snapshot.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        {
+            let mut file = File::create(&temporary)?;
+            let sequence = self.log.sequence.to_le_bytes();
+            file.write_all(&sequence)?;
+            file.write_all(&crc32(&sequence).to_le_bytes())?;
+            self.list.write_snapshot(&mut file)?;
+            file.sync_all()?;
+        }
+        fs::rename(&temporary, &snapshot)?;
+        sync_parent(&snapshot)?;
+        // records up to `sequence` are skipped on replay from now on
+        self.log.clear()
+    }
+}
+
+// Change recorded in a log.
+pub(crate) enum Change<K, V> {
+    Insert(K, V),
+    Delete(K),
+}
+
+// Write-ahead log of numbered and checksummed changes.
+pub(crate) struct Log<K, V> {
+    file: File,
+    // length of the log up to the end of the last complete record
+    len: u64,
+    sync: SyncPolicy,
+    // changes written since the log was last synced
+    unsynced: usize,
+    // number of the last change recorded
+    sequence: u64,
+    // buffer for records reused across changes
+    record: Vec<u8>,
+    marker: PhantomData<fn(K, V)>,
+}
+
+impl<K: Codec, V: Codec> Log<K, V> {
+    // Open the log at `path`, creating it if it does not exist, and pass the
+    // changes logged after change `base` to `apply` in the order they were made.
+    pub(crate) fn open<F>(
+        path: &Path,
+        sync: SyncPolicy,
+        base: u64,
+        mut apply: F,
+    ) -> io::Result<Log<K, V>>
+    where
+        F: FnMut(Change<K, V>),
+    {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let (len, last) = replay(&mut file, base, &mut apply)?;
+        Ok(Log {
+            file,
+            len,
+            sync,
+            unsynced: 0,
+            sequence: last.max(base),
+            record: Vec::new(),
+            marker: PhantomData,
+        })
+    }
+
+    pub(crate) fn insert(&mut self, key: &K, value: &V) -> io::Result<()> {
+        self.append(OP_INSERT, key, Some(value))
+    }
+
+    pub(crate) fn delete(&mut self, key: &K) -> io::Result<()> {
+        self.append(OP_DELETE, key, None)
+    }
+
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    // Remove every record. Change numbers keep counting up.
+    pub(crate) fn clear(&mut self) -> io::Result<()> {
+        self.file.set_len(HEADER_LEN)?;
+        self.file.sync_all()?;
+        self.len = HEADER_LEN;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    // Append a record for the change and sync it as the policy asks.
+    // A record that could not be written completely is cut off again.
+    fn append(&mut self, op: u8, key: &K, value: Option<&V>) -> io::Result<()> {
+        let sequence = self.sequence + 1;
+        let mut record = mem::take(&mut self.record);
+        record.clear();
+        record.extend_from_slice(&[0; RECORD_HEADER_LEN as usize]);
+        record.extend_from_slice(&sequence.to_le_bytes());
+        record.push(op);
+        let encoded = write_field(&mut record, key)
+            .and_then(|_| value.map_or(Ok(()), |value| write_field(&mut record, value)));
+        if let Err(error) = encoded {
+            self.record = record;
+            return Err(error);
+        }
+        let payload = &record[RECORD_HEADER_LEN as usize..];
+        let length = (payload.len() as u32).to_le_bytes();
+        let checksum = crc32(payload);
+        record[..4].copy_from_slice(&length);
+        record[4..8].copy_from_slice(&crc32(&length).to_le_bytes());
+        record[8..12].copy_from_slice(&checksum.to_le_bytes());
+        let written = self.file.write_all(&record);
+        let record_len = record.len() as u64;
+        self.record = record;
+        if let Err(error) = written {
+            let _ = self.file.set_len(self.len);
+            return Err(error);
+        }
+        self.len += record_len;
+        self.sequence = sequence;
+        self.unsynced += 1;
+        let sync = match self.sync {
+            SyncPolicy::Always => true,
+            SyncPolicy::Batch(n) => self.unsynced >= n,
+            SyncPolicy::Never => false,
+        };
+        if sync {
+            self.sync()?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> Drop for Log<K, V> {
+    fn drop(&mut self) {
+        if self.unsynced > 0 && self.sync != SyncPolicy::Never {
+            let _ = self.file.sync_data();
+        }
+    }
+}
+
+fn snapshot_path(path: &Path) -> PathBuf {
+    let mut snapshot: OsString = path.as_os_str().to_owned();
+    snapshot.push(".snapshot");
+    PathBuf::from(snapshot)
+}
+
+// Make a rename in the directory of `path` durable.
+pub(crate) fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+// Load a checkpoint written by `DurableSkipList::checkpoint` along with the
+// number of the last change it includes.
+fn read_checkpoint<K, V>(file: File) -> io::Result<(SkipList<K, V>, u64)>
+where
+    K: Ord + Clone + Codec,
+    V: Clone + Codec,
+{
+    let mut input = BufReader::new(file);
+    let sequence: [u8; 8] = read_array(&mut input)?;
+    let checksum = u32::from_le_bytes(read_array(&mut input)?);
+    if checksum != crc32(&sequence) {
+        return Err(invalid_data("checkpoint checksum mismatch"));
+    }
+    let list = SkipList::read_snapshot(input)?;
+    Ok((list, u64::from_le_bytes(sequence)))
+}
+
+// Pass the changes logged after change `base` to `apply`.
+// Returns the length of the valid part of the log and the number of the last
+// logged change. An incomplete record at the end of the log is cut off, any
+// other damage is reported since cutting it off would lose the records after it.
+// The length of every record is checksummed on its own, so a corrupt length
+// cannot pass a record in the middle of the log off as the torn last one.
+fn replay<K, V, F>(log: &mut File, base: u64, apply: &mut F) -> io::Result<(u64, u64)>
+where
+    K: Codec,
+    V: Codec,
+    F: FnMut(Change<K, V>),
+{
+    let file_len = log.metadata()?.len();
+    if file_len < HEADER_LEN {
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        let mut existing = Vec::new();
+        (&*log).read_to_end(&mut existing)?;
+        // only a new log or one whose creation was interrupted is written over,
+        // any other short file is left alone
+        if !header.starts_with(&existing) {
+            return Err(invalid_data("not a skip list log"));
+        }
+        log.set_len(0)?;
+        log.write_all(&header)?;
+        log.sync_all()?;
+        return Ok((HEADER_LEN, 0));
+    }
+    let mut input = BufReader::new(&*log);
+    let magic: [u8; 8] = read_array(&mut input)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a skip list log"));
+    }
+    if u16::from_le_bytes(read_array(&mut input)?) != VERSION {
+        return Err(invalid_data("unsupported log version"));
+    }
+    let mut offset = HEADER_LEN;
+    let mut last = 0;
+    let mut payload = Vec::new();
+    let torn = loop {
+        if offset == file_len {
+            break false;
+        }
+        if file_len - offset < RECORD_HEADER_LEN {
+            break true;
+        }
+        let length: [u8; 4] = read_array(&mut input)?;
+        let length_checksum = u32::from_le_bytes(read_array(&mut input)?);
+        let checksum = u32::from_le_bytes(read_array(&mut input)?);
+        if crc32(&length) != length_checksum {
+            // space the file system allocated for a write that never landed
+            if length == [0; 4] && length_checksum == 0 && checksum == 0 {
+                break true;
+            }
+            return Err(invalid_data("log record length is corrupt"));
+        }
+        let end = offset + RECORD_HEADER_LEN + u32::from_le_bytes(length) as u64;
+        if end > file_len {
+            break true;
+        }
+        let length = end - offset - RECORD_HEADER_LEN;
+        payload.clear();
+        (&mut input).take(length).read_to_end(&mut payload)?;
+        if crc32(&payload) != checksum {
+            if end == file_len {
+                break true;
+            }
+            return Err(invalid_data("log checksum mismatch"));
+        }
+        last = apply_record(&payload, base, apply).map_err(|error| match error.kind() {
+            io::ErrorKind::UnexpectedEof => invalid_data("log record is malformed"),
+            _ => error,
+        })?;
+        offset = end;
+    };
+    drop(input);
+    if torn {
+        log.set_len(offset)?;
+        log.sync_all()?;
+    }
+    Ok((offset, last))
+}
+
+// Decode a logged change and pass it to `apply` unless it is already part
+// of the checkpoint taken after change `base`. Returns the number of the change.
+fn apply_record<K, V, F>(payload: &[u8], base: u64, apply: &mut F) -> io::Result<u64>
+where
+    K: Codec,
+    V: Codec,
+    F: FnMut(Change<K, V>),
+{
+    let mut fields = payload;
+    let sequence = u64::from_le_bytes(read_array(&mut fields)?);
+    if sequence <= base {
+        return Ok(sequence);
+    }
+    let [op] = read_array(&mut fields)?;
+    let mut key = Vec::new();
+    read_field(&mut fields, &mut key)?;
+    match op {
+        OP_INSERT => {
+            let mut value = Vec::new();
+            read_field(&mut fields, &mut value)?;
+            apply(Change::Insert(K::decode(&key)?, V::decode(&value)?));
+        }
+        OP_DELETE => apply(Change::Delete(K::decode(&key)?)),
+        _ => return Err(invalid_data("unknown log record")),
+    }
+    Ok(sequence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    // Log path unique to the test that is removed along with its snapshot when dropped.
+    struct TempLog(PathBuf);
+
+    impl TempLog {
+        fn new() -> TempLog {
+            let log = TempLog(temp_path("durable").with_extension("log"));
+            log.remove();
+            log
+        }
+
+        fn remove(&self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(snapshot_path(&self.0));
+        }
+
+        fn open(&self) -> io::Result<DurableSkipList<u64, String>> {
+            DurableSkipList::open(&self.0, SyncPolicy::Always)
+        }
+    }
+
+    impl Drop for TempLog {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    fn fill(list: &mut DurableSkipList<u64, String>, keys: std::ops::Range<u64>) {
+        for key in keys {
+            list.insert(key, format!("value-{}", key)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reopen_replays_log() {
+        let log = TempLog::new();
+        {
+            let mut list = log.open().unwrap();
+            assert!(list.is_empty());
+            fill(&mut list, 0..100);
+            for key in (0..100).step_by(3) {
+                list.delete(&key).unwrap();
+            }
+            list.delete(&1000).unwrap();
+            list.insert(5, "five".to_owned()).unwrap();
+        }
+        let mut list = log.open().unwrap();
+        assert_eq!(list.len(), 67);
+        assert_eq!(list.get(&3), None);
+        assert_eq!(list.get(&4), Some("value-4".to_owned()));
+        let fives: Vec<String> = list
+            .collect()
+            .into_iter()
+            .filter(|(key, _)| *key == 5)
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(fives, vec!["value-5".to_owned(), "five".to_owned()]);
+    }
+
+    #[test]
+    fn test_torn_last_record_is_discarded() {
+        let log = TempLog::new();
+        {
+            let mut list = log.open().unwrap();
+            fill(&mut list, 0..10);
+        }
+        let len = fs::metadata(&log.0).unwrap().len();
+        // cut the last record in half
+        OpenOptions::new()
+            .write(true)
+            .open(&log.0)
+            .unwrap()
+            .set_len(len - 6)
+            .unwrap();
+        {
+            let mut list = log.open().unwrap();
+            assert_eq!(list.len(), 9);
+            assert_eq!(list.get(&9), None);
+            list.insert(9, "again".to_owned()).unwrap();
+        }
+        // garbage after the last complete record
+        OpenOptions::new()
+            .append(true)
+            .open(&log.0)
+            .unwrap()
+            .write_all(&[1, 2, 3])
+            .unwrap();
+        let mut list = log.open().unwrap();
+        assert_eq!(list.len(), 10);
+        assert_eq!(list.get(&9), Some("again".to_owned()));
+    }
+
+    #[test]
+    fn test_short_file_is_not_overwritten() {
+        let log = TempLog::new();
+        fs::write(&log.0, b"notes").unwrap();
+        let result = DurableSkipList::<u64, String>::open(&log.0, SyncPolicy::Always);
+        let error = result.err().expect("short file was accepted");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&log.0).unwrap(), b"notes");
+        // an interrupted creation leaves a prefix of the header
+        fs::write(&log.0, &MAGIC[..5]).unwrap();
+        let mut list = DurableSkipList::open(&log.0, SyncPolicy::Always).unwrap();
+        list.insert(1u64, "one".to_owned()).unwrap();
+        drop(list);
+        let list = DurableSkipList::<u64, String>::open(&log.0, SyncPolicy::Always).unwrap();
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn test_corrupt_record_is_an_error() {
+        let log = TempLog::new();
+        {
+            let mut list = log.open().unwrap();
+            fill(&mut list, 0..10);
+        }
+        let mut bytes = fs::read(&log.0).unwrap();
+        bytes[HEADER_LEN as usize + 20] ^= 0xff;
+        fs::write(&log.0, &bytes).unwrap();
+        let error = log.open().err().expect("corruption was not detected");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::write(&log.0, b"definitely not a log").unwrap();
+        let error = log.open().err().expect("foreign file was accepted");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_corrupt_length_in_the_middle_is_an_error() {
+        let log = TempLog::new();
+        {
+            let mut list = log.open().unwrap();
+            fill(&mut list, 0..10);
+        }
+        let bytes = fs::read(&log.0).unwrap();
+        let record_len = (bytes.len() - HEADER_LEN as usize) / 10;
+        // make the length of the fifth record point past the end of the log
+        let mut corrupt = bytes.clone();
+        corrupt[HEADER_LEN as usize + 4 * record_len + 3] ^= 0x40;
+        fs::write(&log.0, &corrupt).unwrap();
+        let error = log.open().err().expect("corruption was not detected");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // the log is left alone for inspection
+        assert_eq!(fs::read(&log.0).unwrap(), corrupt);
+        // a corrupt checksum of the last record is not mistaken for a torn write either
+        let mut corrupt = bytes.clone();
+        corrupt[HEADER_LEN as usize + 9 * record_len + 4] ^= 0x01;
+        fs::write(&log.0, &corrupt).unwrap();
+        let error = log.open().err().expect("corruption was not detected");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // zeroed space after the last record is a torn write
+        let mut zeroed = bytes;
+        zeroed.extend_from_slice(&[0; 32]);
+        fs::write(&log.0, &zeroed).unwrap();
+        assert_eq!(log.open().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let log = TempLog::new();
+        {
+            let mut list = log.open().unwrap();
+            fill(&mut list, 0..500);
+            list.checkpoint().unwrap();
+            assert_eq!(fs::metadata(&log.0).unwrap().len(), HEADER_LEN);
+            fill(&mut list, 500..600);
+            list.delete(&0).unwrap();
+        }
+        let mut list = log.open().unwrap();
+        assert_eq!(list.len(), 599);
+        assert_eq!(list.get(&0), None);
+        assert_eq!(list.get(&599), Some("value-599".to_owned()));
+        list.checkpoint().unwrap();
+        drop(list);
+        assert_eq!(log.open().unwrap().len(), 599);
+    }
+
+    #[test]
+    fn test_crash_during_checkpoint_applies_changes_once() {
+        let log = TempLog::new();
+        let mut list = log.open().unwrap();
+        fill(&mut list, 0..50);
+        let before = fs::read(&log.0).unwrap();
+        list.checkpoint().unwrap();
+        drop(list);
+        // crash after the snapshot was renamed but before the log was emptied
+        fs::write(&log.0, &before).unwrap();
+        let mut list = log.open().unwrap();
+        assert_eq!(list.len(), 50);
+        fill(&mut list, 50..60);
+        drop(list);
+        assert_eq!(log.open().unwrap().len(), 60);
+    }
+
+    #[test]
+    fn test_sync_policies() {
+        for sync in [SyncPolicy::Batch(4), SyncPolicy::Never] {
+            let log = TempLog::new();
+            {
+                let mut list = DurableSkipList::open(&log.0, sync).unwrap();
+                fill(&mut list, 0..10);
+                if sync == SyncPolicy::Batch(4) {
+                    assert_eq!(list.log.unsynced, 2);
+                }
+                list.sync().unwrap();
+                assert_eq!(list.log.unsynced, 0);
+            }
+            assert_eq!(log.open().unwrap().len(), 10);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem;
+
+/// Heap memory owned by a value, used by lists built through
+/// [`SkipListBuilder::build_measured`](../skiplist/struct.SkipListBuilder.html#method.build_measured)
+/// to account for the memory held by their keys and values.
+///
+/// Only memory the value owns outside of its own `size_of` bytes is reported,
+/// so plain values like integers own no heap memory.
+/// Implementations are expected to be cheap and approximate,
+/// for example by reporting the capacity of a buffer rather than its length.
+///
+/// # Example
+/// ```rust
+/// use subway::heap_size::HeapSize;
+///
+/// struct Document {
+///     id: u64,
+///     body: String,
+/// }
+///
+/// impl HeapSize for Document {
+///     fn heap_size(&self) -> usize {
+///         self.body.heap_size()
+///     }
+/// }
+///
+/// let document = Document { id: 1, body: String::from("hello") };
+/// assert_eq!(document.heap_size(), 5);
+/// ```
+pub trait HeapSize {
+    /// Returns the number of heap bytes owned by the value.
+    fn heap_size(&self) -> usize;
+}
+
+macro_rules! impl_no_heap {
+    ($($ty:ty),*) => {
+        $(
+            impl HeapSize for $ty {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+impl_no_heap!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64
+);
+
+// Borrowed data is owned by someone else.
+impl<T: ?Sized> HeapSize for &T {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        mem::size_of::<T>() + (**self).heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_size(&self) -> usize {
+        self.iter().map(HeapSize::heap_size).sum()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize, C: HeapSize> HeapSize for (A, B, C) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size() + self.2.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_values_own_no_heap() {
+        assert_eq!(42u64.heap_size(), 0);
+        assert_eq!('x'.heap_size(), 0);
+        assert_eq!("borrowed".heap_size(), 0);
+        assert_eq!([1u32; 8].heap_size(), 0);
+    }
+
+    #[test]
+    fn test_containers() {
+        let name = String::with_capacity(16);
+        assert_eq!(name.heap_size(), 16);
+        let numbers: Vec<u32> = Vec::with_capacity(10);
+        assert_eq!(numbers.heap_size(), 40);
+        let words = vec![String::from("ab"), String::from("cde")];
+        assert_eq!(
+            words.heap_size(),
+            words.capacity() * mem::size_of::<String>() + 5
+        );
+        assert_eq!(Box::new(7u64).heap_size(), 8);
+        assert_eq!(Some(String::from("abc")).heap_size(), 3);
+        assert_eq!((1u8, String::from("ab")).heap_size(), 2);
+    }
+}
@@ -8,11 +8,61 @@
 //! to achieve fast read and writes.
 //! For more information about how skip lists work
 //! refer [here](https://en.wikipedia.org/wiki/Skip_list).
+//!
+//! # Features
+//! The `std` feature is enabled by default.
+//! Without it the crate is `no_std` and only needs `alloc`. The
+//! [`ConcurrentSkipList`](concurrent/struct.ConcurrentSkipList.html) and
+//! [`SnapshotSkipList`](snapshot/struct.SnapshotSkipList.html), which rely on
+//! operating system locks, the binary snapshots of the [`codec`](codec/index.html)
+//! module, the [`DurableSkipList`](durable/struct.DurableSkipList.html),
+//! the [`MvccSkipList`](mvcc/struct.MvccSkipList.html), the tables of the
+//! [`sstable`](sstable/index.html) module and the
+//! [`LsmTree`](lsm/struct.LsmTree.html) are not available and lists that are not seeded
+//! through [`SkipListBuilder`](skiplist/struct.SkipListBuilder.html) draw tower
+//! heights from a fixed seed.
+//!
+//! The optional `serde` feature implements `Serialize` and `Deserialize`
+//! for [`SkipList`](skiplist/struct.SkipList.html), see the
+//! [`serialization`](serialization/index.html) module.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod codec;
+#[cfg(feature = "std")]
+pub mod concurrent;
+#[cfg(feature = "std")]
+mod crc;
+pub mod deterministic;
+#[cfg(feature = "std")]
+pub mod durable;
+pub mod heap_size;
+#[cfg(feature = "std")]
+pub mod lsm;
+pub mod memtable;
+pub mod merge;
+#[cfg(feature = "std")]
+pub mod mvcc;
+pub mod persistent;
+mod rng;
+#[cfg(feature = "serde")]
+pub mod serialization;
 pub mod skiplist;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod sstable;
+#[cfg(test)]
+mod test_util;
+pub mod unrolled;
 
 #[cfg(test)]
 mod tests {
+    use crate::deterministic::DeterministicSkipList;
     use crate::skiplist::SkipList;
+    use crate::unrolled::UnrolledSkipList;
 
     #[test]
     fn test_skiplist() {
@@ -60,4 +110,13 @@ mod tests {
         maybe_insertion_key = list.bisect(&0);
         assert!(maybe_insertion_key.is_none());
     }
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_lists_are_send() {
+        assert_send::<SkipList<u64, String>>();
+        assert_send::<DeterministicSkipList<u64, String>>();
+        assert_send::<UnrolledSkipList<u64, String>>();
+    }
 }
@@ -0,0 +1,916 @@
+//! Log-structured merge tree storing ordered entries in a directory,
+//! available with the `std` feature.
+//!
+//! An [`LsmTree`] collects writes in a [`Memtable`] and flushes it to a new
+//! [sorted table](crate::sstable) once the memtable grows past its threshold.
+//! Deletes are written as [tombstones](crate::merge::Entry::Tombstone) that hide
+//! older values of the key until compaction reaches the last level and drops them.
+//! Reads and compactions combine the memtable and tables through a
+//! [`MergingIter`].
+//!
+//! # Compaction
+//! Tables are organized in levels. Level `0` holds flushed tables, newest first,
+//! whose keys may overlap. Every deeper level holds tables with disjoint key ranges
+//! sorted by key, and may grow to [`level_ratio`](LsmTreeBuilder::level_ratio) times
+//! the size of the level above it.
+//!
+//! * Once level `0` holds [`level0_tables`](LsmTreeBuilder::level0_tables) tables,
+//!   all of them are merged with the overlapping tables of level `1`.
+//! * Once a deeper level outgrows its size, one of its tables is merged with the
+//!   overlapping tables of the next level. Every level keeps the last key it
+//!   compacted and picks the table after it next, wrapping around at the end, so
+//!   compactions take turns across the key space, also across reopens.
+//!
+//! Merged entries are written to new tables of at most
+//! [`table_size`](LsmTreeBuilder::table_size) bytes before the inputs are removed.
+//!
+//! # Recovery
+//! The tables making up the tree are listed in a `MANIFEST` file that is replaced
+//! atomically after every flush and compaction, along with the last key compacted
+//! out of every level. Tables that are not listed were
+//! left behind by an interrupted flush or compaction and are removed on open.
+//!
+//! Every write is appended to a write-ahead log before it reaches the memtable.
+//! Opening the tree replays the log into the memtable, and the log is emptied once
+//! the memtable is flushed. The [`SyncPolicy`] of the builder decides how often
+//! the log is synced, see [`DurableSkipList`](crate::durable::DurableSkipList).
+//!
+//! # Example
+//! ```rust
+//! use subway::lsm::LsmTree;
+//!
+//! let path = std::env::temp_dir().join(format!("subway-doc-{}.lsm", std::process::id()));
+//! {
+//!     let mut tree = LsmTree::open(&path)?;
+//!     tree.insert(1u64, "one".to_owned())?;
+//!     tree.insert(2u64, "two".to_owned())?;
+//!     tree.insert(3u64, "three".to_owned())?;
+//!     tree.delete(&2)?;
+//!     tree.flush()?;
+//! }
+//! let mut tree: LsmTree<u64, String> = LsmTree::open(&path)?;
+//! assert_eq!(tree.get(&1)?, Some("one".to_owned()));
+//! assert_eq!(tree.get(&2)?, None);
+//! let keys = tree.iter().map(|entry| entry.map(|(key, _)| key)).collect::<Result<Vec<_>, _>>()?;
+//! assert_eq!(keys, vec![1, 3]);
+//! # drop(tree);
+//! # std::fs::remove_dir_all(&path)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use crate::codec::{invalid_data, read_array, read_field, write_field, Codec};
+use crate::crc::crc32;
+use crate::durable::{sync_parent, Change, Log, SyncPolicy};
+use crate::heap_size::HeapSize;
+use crate::memtable::Memtable;
+use crate::merge::{Entry, MergingIter};
+use crate::sstable::{write_table, Table, TableWriter};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+const MANIFEST: &str = "MANIFEST";
+const MANIFEST_TMP: &str = "MANIFEST.tmp";
+const LOG: &str = "LOG";
+const MAGIC: &[u8; 8] = b"SUBWAYMF";
+const VERSION: u16 = 1;
+const TABLE_EXTENSION: &str = "sst";
+
+/// Builder used to configure when an [`LsmTree`] flushes and compacts.
+///
+/// # Example
+/// ```rust
+/// use subway::lsm::{LsmTree, LsmTreeBuilder};
+///
+/// let path = std::env::temp_dir().join(format!("subway-doc-builder-{}.lsm", std::process::id()));
+/// let tree: LsmTree<u64, String> = LsmTreeBuilder::new()
+///     .memtable_size(1024 * 1024)
+///     .level0_tables(8)
+///     .open(&path)?;
+/// # drop(tree);
+/// # std::fs::remove_dir_all(&path)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct LsmTreeBuilder {
+    memtable_size: usize,
+    level0_tables: usize,
+    table_size: u64,
+    level_size: u64,
+    level_ratio: u64,
+    sync: SyncPolicy,
+}
+
+impl LsmTreeBuilder {
+    /// Create a builder with a 4 MiB memtable, compaction of level `0` at
+    /// `4` tables, 2 MiB tables, a 10 MiB level `1` growing tenfold per level
+    /// and a write-ahead log synced after every write.
+    pub fn new() -> LsmTreeBuilder {
+        LsmTreeBuilder {
+            memtable_size: 4 << 20,
+            level0_tables: 4,
+            table_size: 2 << 20,
+            level_size: 10 << 20,
+            level_ratio: 10,
+            sync: SyncPolicy::Always,
+        }
+    }
+
+    /// Number of bytes after which the memtable is flushed to level `0`.
+    pub fn memtable_size(mut self, bytes: usize) -> LsmTreeBuilder {
+        self.memtable_size = bytes;
+        self
+    }
+
+    /// Number of tables in level `0` that triggers their compaction into level `1`.
+    ///
+    /// # Panics
+    /// If `tables` is zero.
+    pub fn level0_tables(mut self, tables: usize) -> LsmTreeBuilder {
+        assert!(tables > 0, "level0_tables must be at least 1");
+        self.level0_tables = tables;
+        self
+    }
+
+    /// Number of bytes after which compaction starts a new table.
+    pub fn table_size(mut self, bytes: u64) -> LsmTreeBuilder {
+        self.table_size = bytes;
+        self
+    }
+
+    /// Number of bytes level `1` may hold before one of its tables is
+    /// compacted into level `2`.
+    pub fn level_size(mut self, bytes: u64) -> LsmTreeBuilder {
+        self.level_size = bytes;
+        self
+    }
+
+    /// Factor by which the size of every level below level `1` grows.
+    ///
+    /// # Panics
+    /// If `ratio` is smaller than `2`.
+    pub fn level_ratio(mut self, ratio: u64) -> LsmTreeBuilder {
+        assert!(ratio >= 2, "level_ratio must be at least 2");
+        self.level_ratio = ratio;
+        self
+    }
+
+    /// When the write-ahead log is forced to stable storage.
+    pub fn sync(mut self, policy: SyncPolicy) -> LsmTreeBuilder {
+        self.sync = policy;
+        self
+    }
+
+    /// Open the tree stored in the directory at `path`, creating the directory
+    /// if it does not exist, and replay the writes logged since the last flush.
+    ///
+    /// # Returns
+    /// An error of kind [`io::ErrorKind::InvalidData`] if the manifest, the log or
+    /// one of the tables is corrupt.
+    pub fn open<K, V, P>(self, path: P) -> io::Result<LsmTree<K, V>>
+    where
+        K: Ord + Clone + Codec + HeapSize,
+        V: Clone + Codec + HeapSize,
+        P: AsRef<Path>,
+    {
+        let dir = path.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let manifest = match fs::read(dir.join(MANIFEST)) {
+            Ok(bytes) => read_manifest(&bytes)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Manifest {
+                next_id: 0,
+                levels: Vec::new(),
+                compact_pointers: Vec::new(),
+            },
+            Err(error) => return Err(error),
+        };
+        let mut levels = Vec::with_capacity(manifest.levels.len());
+        for level in &manifest.levels {
+            let mut tables = Vec::with_capacity(level.len());
+            for &id in level {
+                tables.push(TableFile::open(&dir, id)?);
+            }
+            levels.push(tables);
+        }
+        if levels.is_empty() {
+            levels.push(Vec::new());
+        }
+        remove_unlisted(&dir, &manifest.levels)?;
+        let mut memtable = Memtable::new(self.memtable_size);
+        let log = Log::open(&dir.join(LOG), self.sync, 0, |change| match change {
+            Change::Insert(key, value) => {
+                memtable.insert(key, Entry::Value(value));
+            }
+            Change::Delete(key) => {
+                memtable.insert(key, Entry::Tombstone);
+            }
+        })?;
+        Ok(LsmTree {
+            memtable,
+            log,
+            dir,
+            options: self,
+            levels,
+            compact_pointers: manifest.compact_pointers,
+            next_id: manifest.next_id,
+        })
+    }
+}
+
+impl Default for LsmTreeBuilder {
+    fn default() -> LsmTreeBuilder {
+        LsmTreeBuilder::new()
+    }
+}
+
+// Table of the tree along with the range of keys it holds.
+struct TableFile<K, V> {
+    id: u64,
+    table: Table<K, Entry<V>>,
+    first_key: K,
+    last_key: K,
+    size: u64,
+}
+
+impl<K, V> TableFile<K, V>
+where
+    K: Ord + Clone + Codec,
+    V: Codec,
+{
+    fn open(dir: &Path, id: u64) -> io::Result<TableFile<K, V>> {
+        let path = table_path(dir, id);
+        let table = Table::open(&path)?;
+        let first_key = table.first_key().cloned();
+        let (first_key, last_key) = match (first_key, table.last_key()?) {
+            (Some(first_key), Some(last_key)) => (first_key, last_key),
+            _ => return Err(invalid_data("table of the tree is empty")),
+        };
+        Ok(TableFile {
+            id,
+            table,
+            first_key,
+            last_key,
+            size: fs::metadata(&path)?.len(),
+        })
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.first_key <= *key && *key <= self.last_key
+    }
+
+    fn overlaps(&self, start: &Bound<K>, end: &Bound<K>) -> bool {
+        let after_start = match start {
+            Bound::Included(start) => self.last_key >= *start,
+            Bound::Excluded(start) => self.last_key > *start,
+            Bound::Unbounded => true,
+        };
+        let before_end = match end {
+            Bound::Included(end) => self.first_key <= *end,
+            Bound::Excluded(end) => self.first_key < *end,
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+}
+
+/// Ordered key-value store on the local filesystem built from a [`Memtable`]
+/// and levels of sorted tables. See the [module documentation](index.html).
+pub struct LsmTree<K, V> {
+    dir: PathBuf,
+    options: LsmTreeBuilder,
+    memtable: Memtable<K, Entry<V>>,
+    // writes applied to the memtable since it was last flushed
+    log: Log<K, V>,
+    levels: Vec<Vec<TableFile<K, V>>>,
+    // last key compacted out of every level
+    compact_pointers: Vec<Option<K>>,
+    // id of the next table to write
+    next_id: u64,
+}
+
+impl<K, V> LsmTree<K, V>
+where
+    K: Ord + Clone + Codec + HeapSize,
+    V: Clone + Codec + HeapSize,
+{
+    /// Open the tree stored in the directory at `path` with the default options
+    /// of [`LsmTreeBuilder`].
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<LsmTree<K, V>> {
+        LsmTreeBuilder::new().open(path)
+    }
+
+    /// Insert the given key and value, replacing the value of an existing key.
+    /// The write is logged first, and the memtable is flushed once it is full.
+    pub fn insert(&mut self, key: K, value: V) -> io::Result<()> {
+        self.log.insert(&key, &value)?;
+        self.memtable.insert(key, Entry::Value(value));
+        self.flush_if_full()
+    }
+
+    /// Delete the value associated with the key by writing a tombstone.
+    /// The write is logged first, and the memtable is flushed once it is full.
+    pub fn delete(&mut self, key: &K) -> io::Result<()> {
+        self.log.delete(key)?;
+        self.memtable.insert(key.clone(), Entry::Tombstone);
+        self.flush_if_full()
+    }
+
+    /// Get the value associated with a key if it exists.
+    /// Tables are searched from the newest to the oldest until the key is found.
+    pub fn get(&mut self, key: &K) -> io::Result<Option<V>> {
+        if let Some(entry) = self.memtable.get(key) {
+            return Ok(entry.into_value());
+        }
+        for table in &self.levels[0] {
+            if table.contains(key) {
+                if let Some(entry) = table.table.get(key)? {
+                    return Ok(entry.into_value());
+                }
+            }
+        }
+        for tables in &self.levels[1..] {
+            let position = tables.partition_point(|table| table.last_key < *key);
+            if let Some(table) = tables.get(position).filter(|table| table.contains(key)) {
+                if let Some(entry) = table.table.get(key)? {
+                    return Ok(entry.into_value());
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Iterate over the entries whose keys fall in the given range, in key order.
+    ///
+    /// The memtable and every table that may hold keys of the range are merged,
+    /// yielding the newest value of every key and skipping deleted keys.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        let start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+        let mut sources: Vec<Source<'_, K, V>> = Vec::with_capacity(self.levels[0].len() + 2);
+        sources.push(Box::new(
+            self.memtable.range((start.clone(), end.clone())).map(Ok),
+        ));
+        for table in &self.levels[0] {
+            if table.overlaps(&start, &end) {
+                sources.push(Box::new(table.table.range((start.clone(), end.clone()))));
+            }
+        }
+        // tables of deeper levels are disjoint, so each level is read as one source
+        for tables in &self.levels[1..] {
+            let overlapping: Vec<&TableFile<K, V>> = tables
+                .iter()
+                .filter(|table| table.overlaps(&start, &end))
+                .collect();
+            let (start, end) = (start.clone(), end.clone());
+            sources.push(Box::new(overlapping.into_iter().flat_map(move |table| {
+                table.table.range((start.clone(), end.clone()))
+            })));
+        }
+        Range {
+            entries: MergingIter::from_sources(sources),
+        }
+    }
+
+    /// Iterate over all entries in key order.
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range(..)
+    }
+
+    /// Write the memtable to a new table in level `0`, even if it is not full,
+    /// empty the write-ahead log and run the compactions that became necessary.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.flush_memtable()?;
+        self.compact()
+    }
+
+    /// Returns the number of tables in every level, starting with level `0`.
+    pub fn levels(&self) -> Vec<usize> {
+        self.levels.iter().map(Vec::len).collect()
+    }
+
+    fn flush_if_full(&mut self) -> io::Result<()> {
+        if self.memtable.should_flush() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // The memtable and the log are emptied only once the table is listed in the
+    // manifest. A crash before the log is emptied replays writes that are already
+    // in the table, which writes the same values again.
+    fn flush_memtable(&mut self) -> io::Result<()> {
+        if self.memtable.is_empty() {
+            return Ok(());
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        write_table(table_path(&self.dir, id), self.memtable.iter())?;
+        self.levels[0].insert(0, TableFile::open(&self.dir, id)?);
+        if let Err(error) = self.write_manifest() {
+            self.levels[0].remove(0);
+            return Err(error);
+        }
+        self.memtable.clear();
+        self.log.clear()
+    }
+
+    fn compact(&mut self) -> io::Result<()> {
+        loop {
+            if self.levels[0].len() >= self.options.level0_tables {
+                self.compact_level(0)?;
+                continue;
+            }
+            let level = (1..self.levels.len())
+                .find(|&level| self.level_size(level) > self.max_level_size(level));
+            match level {
+                Some(level) => self.compact_level(level)?,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // Merge all tables of level 0, or the next table in turn of a deeper level,
+    // with the overlapping tables of the next level.
+    fn compact_level(&mut self, level: usize) -> io::Result<()> {
+        if self.levels.len() == level + 1 {
+            self.levels.push(Vec::new());
+        }
+        let inputs = if level == 0 {
+            0..self.levels[0].len()
+        } else {
+            let next = self.next_compaction(level);
+            next..next + 1
+        };
+        if level > 0 && !inputs.is_empty() {
+            if self.compact_pointers.len() <= level {
+                self.compact_pointers.resize_with(level + 1, || None);
+            }
+            self.compact_pointers[level] = Some(self.levels[level][inputs.start].last_key.clone());
+        }
+        let upper = &self.levels[level][inputs.clone()];
+        let start = upper.iter().map(|table| &table.first_key).min().cloned();
+        let end = upper.iter().map(|table| &table.last_key).max().cloned();
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (Bound::Included(start), Bound::Included(end)),
+            _ => return Ok(()),
+        };
+        let lower: Vec<usize> = (0..self.levels[level + 1].len())
+            .filter(|&index| self.levels[level + 1][index].overlaps(&start, &end))
+            .collect();
+        // tombstones only need to hide values of deeper levels
+        let drop_tombstones = self.levels[level + 2..].iter().all(Vec::is_empty);
+        let outputs = {
+            let mut sources: Vec<Source<'_, K, V>> = Vec::with_capacity(upper.len() + lower.len());
+            for table in upper {
+                sources.push(Box::new(table.table.iter()));
+            }
+            for &index in &lower {
+                sources.push(Box::new(self.levels[level + 1][index].table.iter()));
+            }
+            write_tables(
+                &self.dir,
+                self.options.table_size,
+                &mut self.next_id,
+                MergingIter::from_sources(sources),
+                drop_tombstones,
+            )?
+        };
+        let mut removed: Vec<u64> = self.levels[level]
+            .drain(inputs)
+            .map(|table| table.id)
+            .collect();
+        for &index in lower.iter().rev() {
+            removed.push(self.levels[level + 1].remove(index).id);
+        }
+        self.levels[level + 1].extend(outputs);
+        self.levels[level + 1].sort_by(|a, b| a.first_key.cmp(&b.first_key));
+        self.write_manifest()?;
+        for id in removed {
+            fs::remove_file(table_path(&self.dir, id))?;
+        }
+        Ok(())
+    }
+
+    // Index of the first table of a deeper level after the last key compacted
+    // out of it, starting over at the first table.
+    fn next_compaction(&self, level: usize) -> usize {
+        let tables = &self.levels[level];
+        let next = match self.compact_pointers.get(level) {
+            Some(Some(pointer)) => tables.partition_point(|table| table.first_key <= *pointer),
+            _ => 0,
+        };
+        if next == tables.len() {
+            0
+        } else {
+            next
+        }
+    }
+
+    fn level_size(&self, level: usize) -> u64 {
+        self.levels[level].iter().map(|table| table.size).sum()
+    }
+
+    fn max_level_size(&self, level: usize) -> u64 {
+        let growth = self.options.level_ratio.saturating_pow(level as u32 - 1);
+        self.options.level_size.saturating_mul(growth)
+    }
+
+    // Replace the manifest with one listing the current tables and the last key
+    // compacted out of every level.
+    fn write_manifest(&self) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.next_id.to_le_bytes());
+        bytes.extend_from_slice(&(self.levels.len() as u32).to_le_bytes());
+        for (level, tables) in self.levels.iter().enumerate() {
+            bytes.extend_from_slice(&(tables.len() as u32).to_le_bytes());
+            for table in tables {
+                bytes.extend_from_slice(&table.id.to_le_bytes());
+            }
+            write_field(
+                &mut bytes,
+                self.compact_pointers.get(level).unwrap_or(&None),
+            )?;
+        }
+        bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+        let temporary = self.dir.join(MANIFEST_TMP);
+        {
+            let mut file = File::create(&temporary)?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+        }
+        let manifest = self.dir.join(MANIFEST);
+        fs::rename(&temporary, &manifest)?;
+        sync_parent(&manifest)
+    }
+}
+
+// Contents of the manifest.
+struct Manifest<K> {
+    // id of the next table to write
+    next_id: u64,
+    // table ids of every level
+    levels: Vec<Vec<u64>>,
+    // last key compacted out of every level
+    compact_pointers: Vec<Option<K>>,
+}
+
+fn read_manifest<K: Codec>(bytes: &[u8]) -> io::Result<Manifest<K>> {
+    if bytes.len() < MAGIC.len() + 4 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(invalid_data("not a tree manifest"));
+    }
+    let (contents, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32(contents) != u32::from_le_bytes(read_array(&mut &checksum[..])?) {
+        return Err(invalid_data("manifest checksum mismatch"));
+    }
+    let mut input = &contents[MAGIC.len()..];
+    let malformed = |_| invalid_data("manifest is malformed");
+    if u16::from_le_bytes(read_array(&mut input).map_err(malformed)?) != VERSION {
+        return Err(invalid_data("unsupported manifest version"));
+    }
+    let next_id = u64::from_le_bytes(read_array(&mut input).map_err(malformed)?);
+    let level_count = u32::from_le_bytes(read_array(&mut input).map_err(malformed)?);
+    let mut levels = Vec::new();
+    let mut compact_pointers = Vec::new();
+    let mut field = Vec::new();
+    for _ in 0..level_count {
+        let table_count = u32::from_le_bytes(read_array(&mut input).map_err(malformed)?);
+        let mut ids = Vec::new();
+        for _ in 0..table_count {
+            let id = u64::from_le_bytes(read_array(&mut input).map_err(malformed)?);
+            if id >= next_id {
+                return Err(invalid_data("manifest is malformed"));
+            }
+            ids.push(id);
+        }
+        levels.push(ids);
+        read_field(&mut input, &mut field).map_err(malformed)?;
+        compact_pointers.push(Option::<K>::decode(&field)?);
+    }
+    if !input.is_empty() {
+        return Err(invalid_data("manifest is malformed"));
+    }
+    Ok(Manifest {
+        next_id,
+        levels,
+        compact_pointers,
+    })
+}
+
+// Remove tables left behind by an interrupted flush or compaction,
+// along with a manifest that was never put in place.
+fn remove_unlisted(dir: &Path, levels: &[Vec<u64>]) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|name| name == MANIFEST_TMP) {
+            fs::remove_file(&path)?;
+            continue;
+        }
+        if path
+            .extension()
+            .is_none_or(|extension| extension != TABLE_EXTENSION)
+        {
+            continue;
+        }
+        let id = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok());
+        if let Some(id) = id {
+            if !levels.iter().any(|ids| ids.contains(&id)) {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn table_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:06}.{}", id, TABLE_EXTENSION))
+}
+
+type TableOutput<K, V> = (u64, TableWriter<K, Entry<V>, BufWriter<File>>);
+
+fn create_table<K, V>(dir: &Path, next_id: &mut u64) -> io::Result<TableOutput<K, V>>
+where
+    K: Ord + Clone + Codec,
+    V: Codec,
+{
+    let id = *next_id;
+    *next_id += 1;
+    let file = File::create(table_path(dir, id))?;
+    Ok((id, TableWriter::new(BufWriter::new(file))))
+}
+
+fn finish_table<K, V>(dir: &Path, (id, writer): TableOutput<K, V>) -> io::Result<TableFile<K, V>>
+where
+    K: Ord + Clone + Codec,
+    V: Codec,
+{
+    let file = writer
+        .finish()?
+        .into_inner()
+        .map_err(|error| error.into_error())?;
+    file.sync_all()?;
+    TableFile::open(dir, id)
+}
+
+// Write the merged entries to new tables of about `table_size` bytes each.
+fn write_tables<K, V>(
+    dir: &Path,
+    table_size: u64,
+    next_id: &mut u64,
+    mut entries: MergingIter<Source<'_, K, V>, K, V>,
+    drop_tombstones: bool,
+) -> io::Result<Vec<TableFile<K, V>>>
+where
+    K: Ord + Clone + Codec,
+    V: Codec,
+{
+    let mut tables = Vec::new();
+    let mut output = create_table(dir, next_id)?;
+    while let Some(entry) = entries.next_entry() {
+        let (key, entry) = entry?;
+        if entry.is_tombstone() && drop_tombstones {
+            continue;
+        }
+        output.1.add(&key, &entry)?;
+        if output.1.size() >= table_size {
+            let full = std::mem::replace(&mut output, create_table(dir, next_id)?);
+            tables.push(finish_table(dir, full)?);
+        }
+    }
+    if output.1.is_empty() {
+        fs::remove_file(table_path(dir, output.0))?;
+    } else {
+        tables.push(finish_table(dir, output)?);
+    }
+    Ok(tables)
+}
+
+type Source<'a, K, V> = Box<dyn Iterator<Item = io::Result<(K, Entry<V>)>> + 'a>;
+
+/// Iterator over the entries of an [`LsmTree`] in key order, created by
+/// [`LsmTree::range`]. Iteration ends after the first error.
+pub struct Range<'a, K, V> {
+    entries: MergingIter<Source<'a, K, V>, K, V>,
+}
+
+impl<K: Ord, V> Iterator for Range<'_, K, V> {
+    type Item = io::Result<(K, V)>;
+
+    fn next(&mut self) -> Option<io::Result<(K, V)>> {
+        self.entries.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use std::collections::BTreeMap;
+
+    // Tree directory unique to the test that is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> TempDir {
+            let dir = TempDir(temp_path("lsm"));
+            let _ = fs::remove_dir_all(&dir.0);
+            dir
+        }
+
+        // Small sizes so that tests flush and compact often.
+        fn open(&self) -> LsmTree<u64, String> {
+            LsmTreeBuilder::new()
+                .memtable_size(4 * 1024)
+                .level0_tables(3)
+                .table_size(8 * 1024)
+                .level_size(16 * 1024)
+                .level_ratio(4)
+                .sync(SyncPolicy::Never)
+                .open(&self.0)
+                .unwrap()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn collect(range: Range<'_, u64, String>) -> Vec<(u64, String)> {
+        range.map(|entry| entry.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_matches_btree_map() {
+        let dir = TempDir::new();
+        let mut tree = dir.open();
+        let mut expected = BTreeMap::new();
+        for round in 0..4u64 {
+            for i in 0..1500u64 {
+                let key = (i * 7919 + round) % 2000;
+                if (i + round) % 5 == 0 {
+                    tree.delete(&key).unwrap();
+                    expected.remove(&key);
+                } else {
+                    let value = format!("value-{}-{}", key, round);
+                    tree.insert(key, value.clone()).unwrap();
+                    expected.insert(key, value);
+                }
+            }
+        }
+        assert!(tree.levels().len() > 2, "{:?}", tree.levels());
+        assert!(tree.levels()[0] < 3);
+        for tables in &tree.levels[1..] {
+            for pair in tables.windows(2) {
+                assert!(pair[0].last_key < pair[1].first_key);
+            }
+        }
+        for key in 0..2100 {
+            assert_eq!(tree.get(&key).unwrap(), expected.get(&key).cloned());
+        }
+        let all: Vec<(u64, String)> = expected.clone().into_iter().collect();
+        assert_eq!(collect(tree.iter()), all);
+        let some: Vec<(u64, String)> = expected
+            .range(100..=300)
+            .map(|(key, value)| (*key, value.clone()))
+            .collect();
+        assert_eq!(collect(tree.range(100..=300)), some);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = TempDir::new();
+        {
+            let mut tree = dir.open();
+            for i in 0..1000u64 {
+                tree.insert(i, format!("value-{}", i)).unwrap();
+            }
+            for i in (0..1000u64).step_by(3) {
+                tree.delete(&i).unwrap();
+            }
+            tree.flush().unwrap();
+        }
+        let mut tree = dir.open();
+        assert_eq!(tree.get(&1).unwrap(), Some("value-1".to_owned()));
+        assert_eq!(tree.get(&3).unwrap(), None);
+        assert_eq!(tree.iter().count(), 666);
+        tree.insert(3, "three".to_owned()).unwrap();
+        assert_eq!(tree.get(&3).unwrap(), Some("three".to_owned()));
+    }
+
+    #[test]
+    fn test_replays_unflushed_writes() {
+        let dir = TempDir::new();
+        {
+            let mut tree = dir.open();
+            tree.insert(1, "one".to_owned()).unwrap();
+            tree.insert(2, "two".to_owned()).unwrap();
+            tree.flush().unwrap();
+            tree.delete(&1).unwrap();
+            tree.insert(3, "three".to_owned()).unwrap();
+            tree.insert(2, "deux".to_owned()).unwrap();
+        }
+        let mut tree = dir.open();
+        assert_eq!(tree.levels(), vec![1]);
+        assert_eq!(tree.get(&1).unwrap(), None);
+        assert_eq!(
+            collect(tree.iter()),
+            vec![(2, "deux".to_owned()), (3, "three".to_owned())]
+        );
+        // the log is emptied by the flush, so nothing is replayed twice
+        tree.flush().unwrap();
+        drop(tree);
+        let tree = dir.open();
+        assert_eq!(tree.levels(), vec![2]);
+        assert_eq!(tree.memtable.len(), 0);
+    }
+
+    #[test]
+    fn test_compacts_levels_round_robin() {
+        let dir = TempDir::new();
+        let mut tree = dir.open();
+        for i in 0..3000u64 {
+            tree.insert(i, format!("value-{}", i)).unwrap();
+        }
+        assert!(tree.levels[1].len() > 2, "{:?}", tree.levels());
+        let mut compacted = Vec::new();
+        for _ in 0..tree.levels[1].len() {
+            let next = &tree.levels[1][tree.next_compaction(1)];
+            compacted.push((next.first_key, next.last_key));
+            tree.compact_level(1).unwrap();
+        }
+        // every compaction picks up after the keys of the previous one
+        for pair in compacted.windows(2) {
+            assert!(pair[0].1 < pair[1].0, "{:?}", compacted);
+        }
+        // the manifest keeps the pointer across reopens
+        let pointer = tree.compact_pointers[1];
+        assert!(pointer.is_some());
+        drop(tree);
+        let mut tree = dir.open();
+        assert_eq!(tree.compact_pointers[1], pointer);
+        for i in 0..3000u64 {
+            assert_eq!(tree.get(&i).unwrap(), Some(format!("value-{}", i)));
+        }
+    }
+
+    #[test]
+    fn test_tombstones_hide_flushed_values() {
+        let dir = TempDir::new();
+        let mut tree = dir.open();
+        tree.insert(1, "one".to_owned()).unwrap();
+        tree.insert(2, "two".to_owned()).unwrap();
+        tree.flush().unwrap();
+        tree.delete(&1).unwrap();
+        assert_eq!(tree.get(&1).unwrap(), None);
+        assert_eq!(collect(tree.iter()), vec![(2, "two".to_owned())]);
+        tree.flush().unwrap();
+        assert_eq!(tree.levels(), vec![2]);
+        assert_eq!(tree.get(&1).unwrap(), None);
+        assert_eq!(collect(tree.range(..2)), vec![]);
+        // compacting into the last level drops the tombstone
+        tree.delete(&2).unwrap();
+        tree.flush().unwrap();
+        assert_eq!(tree.levels(), vec![0, 0]);
+        assert_eq!(tree.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_removes_unlisted_tables() {
+        let dir = TempDir::new();
+        {
+            let mut tree = dir.open();
+            tree.insert(1, "one".to_owned()).unwrap();
+            tree.flush().unwrap();
+        }
+        let leftover = table_path(&dir.0, 100);
+        fs::write(&leftover, b"partial").unwrap();
+        fs::write(dir.0.join(MANIFEST_TMP), b"partial").unwrap();
+        let mut tree = dir.open();
+        assert!(!leftover.exists());
+        assert!(!dir.0.join(MANIFEST_TMP).exists());
+        assert_eq!(tree.get(&1).unwrap(), Some("one".to_owned()));
+    }
+
+    #[test]
+    fn test_detects_corrupt_manifest() {
+        let dir = TempDir::new();
+        {
+            let mut tree = dir.open();
+            tree.insert(1, "one".to_owned()).unwrap();
+            tree.flush().unwrap();
+        }
+        let manifest = dir.0.join(MANIFEST);
+        let mut bytes = fs::read(&manifest).unwrap();
+        bytes[12] ^= 1;
+        fs::write(&manifest, &bytes).unwrap();
+        let result = LsmTree::<u64, String>::open(&dir.0);
+        let error = result.err().expect("corruption was not detected");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
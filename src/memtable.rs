@@ -0,0 +1,295 @@
+use crate::heap_size::HeapSize;
+use crate::skiplist::{Iter, Range, SkipList, SkipListBuilder};
+use alloc::sync::Arc;
+use core::iter::Cloned;
+use core::mem;
+use core::ops::RangeBounds;
+use core::slice;
+
+/// Write buffer of an LSM storage engine built on a [`SkipList`].
+///
+/// Keys are unique, inserting an existing key replaces its value.
+/// The memtable tracks the memory used by its entries through [`HeapSize`]
+/// and reports when it has grown past its flush threshold.
+/// [`freeze`](#method.freeze) then hands the entries over as a read-only
+/// [`FrozenMemtable`] to be flushed, while a fresh list takes new writes.
+///
+/// # Example
+/// ```rust
+/// use subway::memtable::Memtable;
+///
+/// let mut memtable = Memtable::new(1024);
+/// for i in 0..100u64 {
+///     memtable.insert(i, format!("value-{}", i));
+/// }
+/// assert!(memtable.should_flush());
+/// let frozen = memtable.freeze();
+/// assert!(memtable.is_empty());
+/// assert_eq!(frozen.get(&42), Some("value-42".to_owned()));
+/// // entries are flushed in key order
+/// let flushed: Vec<(u64, String)> = frozen.iter().collect();
+/// assert_eq!(flushed.len(), 100);
+/// ```
+pub struct Memtable<K, V> {
+    list: SkipList<K, V>,
+    flush_threshold: usize,
+}
+
+impl<K, V> Memtable<K, V>
+where
+    K: Ord + Clone + HeapSize,
+    V: Clone + HeapSize,
+{
+    /// Create an empty memtable that should be flushed once it uses
+    /// `flush_threshold` bytes.
+    pub fn new(flush_threshold: usize) -> Memtable<K, V> {
+        Memtable {
+            list: SkipListBuilder::new().build_measured(),
+            flush_threshold,
+        }
+    }
+
+    /// Insert the given key and value.
+    ///
+    /// # Returns
+    /// The value previously associated with the key, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.list.upsert(key, value)
+    }
+
+    /// Get the value associated with a key if it exists.
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.list.get(key)
+    }
+
+    /// Remove the key from the memtable.
+    ///
+    /// # Returns
+    /// The value associated with the key, if any.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.list.get(key)?;
+        self.list.delete(key);
+        Some(value)
+    }
+
+    /// Iterate over the entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.list.iter()
+    }
+
+    /// Iterate over the entries whose keys fall in the given range, in key order.
+    /// See [`SkipList::range`].
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        self.list.range(range)
+    }
+
+    /// Returns the number of entries in the memtable.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Returns whether memtable is empty.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Returns the approximate number of bytes used by the memtable.
+    /// See [`SkipList::memory_usage`].
+    pub fn memory_usage(&self) -> usize {
+        self.list.memory_usage()
+    }
+
+    /// Returns the number of bytes after which the memtable should be flushed.
+    pub fn flush_threshold(&self) -> usize {
+        self.flush_threshold
+    }
+
+    /// Returns whether the memtable has grown past its flush threshold.
+    pub fn should_flush(&self) -> bool {
+        self.memory_usage() >= self.flush_threshold
+    }
+
+    /// Remove every entry, keeping the flush threshold. Use it in place of
+    /// [`freeze`](#method.freeze) when the entries are no longer needed, such
+    /// as after they were written out, to avoid moving them into a table.
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    /// Turn the current entries into a read-only table and continue with an
+    /// empty memtable. Runs in linear time, entries are moved rather than cloned.
+    pub fn freeze(&mut self) -> FrozenMemtable<K, V> {
+        let mut list = mem::replace(&mut self.list, SkipListBuilder::new().build_measured());
+        FrozenMemtable {
+            memory: list.memory_usage(),
+            entries: list.drain().into(),
+        }
+    }
+}
+
+/// Read-only entries of a [`Memtable`] returned by [`Memtable::freeze`].
+///
+/// The entries are kept in a sorted array that is `Send` and `Sync`, so a
+/// frozen memtable can be moved to the thread that flushes it and cloned
+/// cheaply to serve reads from other threads until the flush completes.
+pub struct FrozenMemtable<K, V> {
+    entries: Arc<[(K, V)]>,
+    // memory used by the memtable when it was frozen
+    memory: usize,
+}
+
+impl<K, V> FrozenMemtable<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    /// Get the value associated with a key if it exists.
+    /// Runs in `O(log n)` through a binary search.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let index = self
+            .entries
+            .binary_search_by(|(entry, _)| entry.cmp(key))
+            .ok()?;
+        Some(self.entries[index].1.clone())
+    }
+
+    /// Iterate over the entries in key order.
+    pub fn iter(&self) -> Cloned<slice::Iter<'_, (K, V)>> {
+        self.entries.iter().cloned()
+    }
+
+    /// Returns the number of entries in the table.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the approximate number of bytes the entries used in the memtable.
+    pub fn memory_usage(&self) -> usize {
+        self.memory
+    }
+}
+
+impl<K, V> Clone for FrozenMemtable<K, V> {
+    fn clone(&self) -> FrozenMemtable<K, V> {
+        FrozenMemtable {
+            entries: Arc::clone(&self.entries),
+            memory: self.memory,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_send_sync;
+    use std::thread;
+
+    #[test]
+    fn test_frozen_is_send_and_sync() {
+        assert_send_sync::<FrozenMemtable<u64, String>>();
+    }
+
+    #[test]
+    fn test_frozen_is_shared_between_readers() {
+        let mut memtable = Memtable::new(usize::MAX);
+        for i in 0..1000u64 {
+            memtable.insert(i, i.to_string());
+        }
+        let frozen = memtable.freeze();
+        thread::scope(|scope| {
+            for reader in 0..4u64 {
+                let frozen = &frozen;
+                scope.spawn(move || {
+                    for i in (reader..1000).step_by(4) {
+                        assert_eq!(frozen.get(&i), Some(i.to_string()));
+                    }
+                });
+            }
+        });
+        let flushing = frozen.clone();
+        assert_eq!(
+            thread::spawn(move || flushing.iter().count())
+                .join()
+                .unwrap(),
+            1000
+        );
+    }
+
+    #[test]
+    fn test_keys_are_unique() {
+        let mut memtable = Memtable::new(usize::MAX);
+        for i in (0..100u64).rev() {
+            assert_eq!(memtable.insert(i, i.to_string()), None);
+        }
+        assert_eq!(memtable.insert(5, "five".to_owned()), Some("5".to_owned()));
+        assert_eq!(memtable.get(&5), Some("five".to_owned()));
+        assert_eq!(memtable.len(), 100);
+        assert_eq!(memtable.remove(&5), Some("five".to_owned()));
+        assert_eq!(memtable.remove(&5), None);
+        let keys: Vec<u64> = memtable.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys.len(), 99);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        let keys: Vec<u64> = memtable.range(3..8).map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![3, 4, 6, 7]);
+    }
+
+    #[test]
+    fn test_flush_threshold() {
+        let mut memtable = Memtable::new(64 * 1024);
+        let mut written = 0;
+        while !memtable.should_flush() {
+            memtable.insert(written, vec![0u8; 1000]);
+            written += 1;
+        }
+        assert!(memtable.memory_usage() >= memtable.flush_threshold());
+        // every entry holds a kilobyte, so the threshold is reached
+        // after at most 64 entries
+        assert!(written > 0 && written <= 64);
+        let frozen = memtable.freeze();
+        assert!(!memtable.should_flush());
+        assert_eq!(frozen.len(), written as usize);
+        assert!(frozen.memory_usage() >= 64 * 1000);
+        while !memtable.should_flush() {
+            memtable.insert(written, vec![0u8; 1000]);
+            written += 1;
+        }
+        memtable.clear();
+        assert!(memtable.is_empty());
+        assert!(!memtable.should_flush());
+        assert_eq!(
+            memtable.memory_usage(),
+            Memtable::<u64, Vec<u8>>::new(0).memory_usage()
+        );
+    }
+
+    #[test]
+    fn test_freeze_hands_off_entries() {
+        let mut memtable = Memtable::new(usize::MAX);
+        for i in 0..1000u64 {
+            memtable.insert(i, i * 10);
+        }
+        let frozen = memtable.freeze();
+        assert!(memtable.is_empty());
+        memtable.insert(1, 0);
+        assert_eq!(frozen.get(&1), Some(10));
+        assert_eq!(frozen.get(&1000), None);
+        let flusher = thread::spawn(move || {
+            frozen
+                .iter()
+                .map(|(key, value)| {
+                    assert_eq!(value, key * 10);
+                    key
+                })
+                .collect::<Vec<u64>>()
+        });
+        let flushed = flusher.join().unwrap();
+        assert_eq!(flushed, (0..1000).collect::<Vec<u64>>());
+        assert_eq!(memtable.get(&1), Some(0));
+        assert!(Memtable::<u64, u64>::new(0).freeze().is_empty());
+    }
+}
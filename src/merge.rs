@@ -0,0 +1,408 @@
+use crate::heap_size::HeapSize;
+use crate::skiplist::{Iter, SkipList};
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::convert::Infallible;
+
+/// Value of a [`SkipList`] or table layered over older ones.
+///
+/// A [`Tombstone`](Entry::Tombstone) marks its key as deleted and hides the
+/// values older lists hold for it when the lists are read through a [`MergingIter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Entry<V> {
+    /// The value of the key.
+    Value(V),
+    /// Marker shadowing every older value of the key.
+    Tombstone,
+}
+
+impl<V> Entry<V> {
+    /// Returns whether the entry marks its key as deleted.
+    pub fn is_tombstone(&self) -> bool {
+        matches!(self, Entry::Tombstone)
+    }
+
+    /// Returns the value, or `None` for a tombstone.
+    pub fn value(&self) -> Option<&V> {
+        match self {
+            Entry::Value(value) => Some(value),
+            Entry::Tombstone => None,
+        }
+    }
+
+    /// Converts the entry into its value, or `None` for a tombstone.
+    pub fn into_value(self) -> Option<V> {
+        match self {
+            Entry::Value(value) => Some(value),
+            Entry::Tombstone => None,
+        }
+    }
+}
+
+impl<V> From<Option<V>> for Entry<V> {
+    fn from(value: Option<V>) -> Entry<V> {
+        match value {
+            Some(value) => Entry::Value(value),
+            None => Entry::Tombstone,
+        }
+    }
+}
+
+impl<V: HeapSize> HeapSize for Entry<V> {
+    fn heap_size(&self) -> usize {
+        self.value().map_or(0, HeapSize::heap_size)
+    }
+}
+
+/// Item of a sorted source read by a [`MergingIter`].
+///
+/// Implemented for `(K, Entry<V>)`, the items of infallible sources such as a
+/// [`SkipList`], and for `Result<(K, Entry<V>), E>`, the items of sources that
+/// can fail such as an on-disk table. The merged sequence yields items of the
+/// same kind: `(K, V)` for the former and `Result<(K, V), E>` for the latter.
+pub trait MergeItem<K, V> {
+    /// Error a source may return instead of an entry.
+    type Error;
+    /// Item yielded by the [`MergingIter`] for every key holding a value.
+    type Output;
+
+    /// Returns the entry held by the item.
+    fn into_entry(self) -> Result<(K, Entry<V>), Self::Error>;
+
+    /// Wraps a merged key and value, or an error of a source, into an output item.
+    fn output(entry: Result<(K, V), Self::Error>) -> Self::Output;
+}
+
+impl<K, V> MergeItem<K, V> for (K, Entry<V>) {
+    type Error = Infallible;
+    type Output = (K, V);
+
+    fn into_entry(self) -> Result<(K, Entry<V>), Infallible> {
+        Ok(self)
+    }
+
+    fn output(entry: Result<(K, V), Infallible>) -> (K, V) {
+        match entry {
+            Ok(entry) => entry,
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl<K, V, E> MergeItem<K, V> for Result<(K, Entry<V>), E> {
+    type Error = E;
+    type Output = Result<(K, V), E>;
+
+    fn into_entry(self) -> Result<(K, Entry<V>), E> {
+        self
+    }
+
+    fn output(entry: Result<(K, V), E>) -> Result<(K, V), E> {
+        entry
+    }
+}
+
+// Error returned by the sources of a `MergingIter<S, K, V>`.
+type SourceError<S, K, V> = <<S as Iterator>::Item as MergeItem<K, V>>::Error;
+
+// Winning entry of a key, or the error of a source.
+type Merged<S, K, V> = Result<(K, Entry<V>), SourceError<S, K, V>>;
+
+// Next entry of a source, ordered so that the smallest key of the newest source
+// is at the top of the heap.
+struct Head<K, V> {
+    key: K,
+    entry: Entry<V>,
+    // position of the source, `0` for the newest
+    source: usize,
+}
+
+impl<K: Ord, V> Ord for Head<K, V> {
+    fn cmp(&self, other: &Head<K, V>) -> Ordering {
+        other
+            .key
+            .cmp(&self.key)
+            .then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+impl<K: Ord, V> PartialOrd for Head<K, V> {
+    fn partial_cmp(&self, other: &Head<K, V>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> PartialEq for Head<K, V> {
+    fn eq(&self, other: &Head<K, V>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord, V> Eq for Head<K, V> {}
+
+/// Iterator merging several sorted sources of [`Entry`] values, such as
+/// [`SkipList`]s, into one sequence in key order.
+///
+/// Sources are given from the newest to the oldest. For every key the entry of
+/// the newest source holding it wins, and keys whose winning entry is a
+/// [`Tombstone`](Entry::Tombstone) are skipped. When a source holds a key more
+/// than once, the entry it yields last wins, which for a [`SkipList`] is the one
+/// inserted last and returned by [`SkipList::get`].
+///
+/// Sources may fail, see [`MergeItem`]. Iteration ends after the first error,
+/// which is returned as soon as it is read: a source is read one entry past
+/// the key it yields, to find later entries holding the same key.
+///
+/// Each step takes `O(log k)` time for `k` sources.
+///
+/// # Example
+/// ```rust
+/// use subway::merge::{Entry, MergingIter};
+/// use subway::skiplist::SkipList;
+///
+/// let mut base = SkipList::new();
+/// for i in 0..5 {
+///     base.insert(i, Entry::Value(i * 10));
+/// }
+/// let mut recent = SkipList::new();
+/// recent.insert(1, Entry::Value(11));
+/// recent.insert(3, Entry::Tombstone);
+/// recent.insert(7, Entry::Value(70));
+///
+/// let merged: Vec<(i32, i32)> = MergingIter::new(vec![&recent, &base]).collect();
+/// assert_eq!(merged, vec![(0, 0), (1, 11), (2, 20), (4, 40), (7, 70)]);
+/// ```
+pub struct MergingIter<S, K, V> {
+    sources: Vec<S>,
+    heads: BinaryHeap<Head<K, V>>,
+    // sources are read for their first entry on the first step, so that an
+    // error is returned from the iterator rather than its constructor
+    started: bool,
+    done: bool,
+}
+
+impl<'a, K, V> MergingIter<Iter<'a, K, Entry<V>>, K, V>
+where
+    K: Ord + Clone + 'a,
+    V: Clone + 'a,
+{
+    /// Create an iterator over the given lists, ordered from the newest to the oldest.
+    pub fn new<I>(lists: I) -> MergingIter<Iter<'a, K, Entry<V>>, K, V>
+    where
+        I: IntoIterator<Item = &'a SkipList<K, Entry<V>>>,
+    {
+        MergingIter::from_sources(lists.into_iter().map(SkipList::iter))
+    }
+}
+
+impl<S, K, V> MergingIter<S, K, V>
+where
+    S: Iterator,
+    S::Item: MergeItem<K, V>,
+    K: Ord,
+{
+    /// Create an iterator over the given sources, ordered from the newest to the
+    /// oldest. Every source must yield its entries sorted by key.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::merge::{Entry, MergingIter};
+    ///
+    /// let recent = vec![Ok((1, Entry::Tombstone)), Ok((2, Entry::Value("two")))];
+    /// let base = vec![
+    ///     Ok((1, Entry::Value("one"))),
+    ///     Ok((3, Entry::Value("three"))),
+    ///     Err("unreadable"),
+    /// ];
+    /// let mut merged = MergingIter::from_sources(vec![recent.into_iter(), base.into_iter()]);
+    /// assert_eq!(merged.next(), Some(Ok((2, "two"))));
+    /// assert_eq!(merged.next(), Some(Err("unreadable")));
+    /// assert_eq!(merged.next(), None);
+    /// ```
+    pub fn from_sources<I>(sources: I) -> MergingIter<S, K, V>
+    where
+        I: IntoIterator<Item = S>,
+    {
+        let sources: Vec<S> = sources.into_iter().collect();
+        MergingIter {
+            heads: BinaryHeap::with_capacity(sources.len()),
+            sources,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Returns the winning entry of the next key, tombstones included, which is
+    /// what a compaction that keeps shadowing older data needs.
+    pub fn next_entry(&mut self) -> Option<Merged<S, K, V>> {
+        if self.done {
+            return None;
+        }
+        let entry = self.merge_next();
+        if !matches!(entry, Some(Ok(_))) {
+            self.done = true;
+        }
+        entry
+    }
+
+    fn merge_next(&mut self) -> Option<Merged<S, K, V>> {
+        if !self.started {
+            self.started = true;
+            for source in 0..self.sources.len() {
+                if let Err(error) = self.skip_key(source, None) {
+                    return Some(Err(error));
+                }
+            }
+        }
+        let mut newest = self.heads.pop()?;
+        match self.skip_key(newest.source, Some(&newest.key)) {
+            Ok(Some(entry)) => newest.entry = entry,
+            Ok(None) => {}
+            Err(error) => return Some(Err(error)),
+        }
+        // drop the entries the newest one shadows
+        while self.heads.peek().is_some_and(|head| head.key == newest.key) {
+            if let Some(shadowed) = self.heads.pop() {
+                if let Err(error) = self.skip_key(shadowed.source, Some(&newest.key)) {
+                    return Some(Err(error));
+                }
+            }
+        }
+        Some(Ok((newest.key, newest.entry)))
+    }
+
+    // Read the source past the entries holding `key` and queue its next entry.
+    // Returns the last entry holding the key.
+    fn skip_key(
+        &mut self,
+        source: usize,
+        key: Option<&K>,
+    ) -> Result<Option<Entry<V>>, SourceError<S, K, V>> {
+        let mut last = None;
+        for item in self.sources[source].by_ref() {
+            let (next, entry) = item.into_entry()?;
+            if key == Some(&next) {
+                last = Some(entry);
+            } else {
+                self.heads.push(Head {
+                    key: next,
+                    entry,
+                    source,
+                });
+                break;
+            }
+        }
+        Ok(last)
+    }
+}
+
+impl<S, K, V> Iterator for MergingIter<S, K, V>
+where
+    S: Iterator,
+    S::Item: MergeItem<K, V>,
+    K: Ord,
+{
+    type Item = <S::Item as MergeItem<K, V>>::Output;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_entry()? {
+                Ok((key, Entry::Value(value))) => return Some(S::Item::output(Ok((key, value)))),
+                Ok((_, Entry::Tombstone)) => continue,
+                Err(error) => return Some(S::Item::output(Err(error))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_entry() {
+        let entry: Entry<i32> = Some(1).into();
+        assert_eq!(entry.value(), Some(&1));
+        assert!(!entry.is_tombstone());
+        let tombstone: Entry<i32> = None.into();
+        assert!(tombstone.is_tombstone());
+        assert_eq!(tombstone.into_value(), None);
+        assert_eq!(Entry::Value(vec![0u8; 8]).heap_size(), 8);
+        assert_eq!(Entry::<Vec<u8>>::Tombstone.heap_size(), 0);
+    }
+
+    #[test]
+    fn test_newest_list_wins() {
+        let mut lists: Vec<SkipList<u64, Entry<u64>>> = (0..4).map(|_| SkipList::new()).collect();
+        // list `n` holds every key divisible by `n + 1`, written at age `n`,
+        // and deletes every key divisible by `n + 5`
+        for (age, list) in lists.iter_mut().enumerate() {
+            let age = age as u64;
+            for key in (0..200).step_by(age as usize + 1) {
+                list.insert(key, Entry::Value(age));
+            }
+            for key in (0..200).step_by(age as usize + 5) {
+                list.delete(&key);
+                list.insert(key, Entry::Tombstone);
+            }
+        }
+        let mut expected = BTreeMap::new();
+        for list in lists.iter().rev() {
+            for (key, entry) in list.iter() {
+                match entry {
+                    Entry::Value(value) => expected.insert(key, value),
+                    Entry::Tombstone => expected.remove(&key),
+                };
+            }
+        }
+        let merged: Vec<(u64, u64)> = MergingIter::new(&lists).collect();
+        assert_eq!(merged, expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_duplicate_keys_and_empty_lists() {
+        let mut recent = SkipList::new();
+        recent.insert(2, Entry::Value("first"));
+        recent.insert(2, Entry::Value("second"));
+        let empty = SkipList::new();
+        let mut base = SkipList::new();
+        base.insert(1, Entry::Tombstone);
+        base.insert(2, Entry::Value("base"));
+        base.insert(3, Entry::Value("three"));
+        let merged: Vec<(i32, &str)> = MergingIter::new(vec![&empty, &recent, &base]).collect();
+        assert_eq!(merged, vec![(2, "second"), (3, "three")]);
+        assert_eq!(recent.get(&2), Some(Entry::Value("second")));
+        let none: Vec<&SkipList<i32, Entry<&str>>> = Vec::new();
+        assert_eq!(MergingIter::new(none).count(), 0);
+    }
+
+    #[test]
+    fn test_fallible_sources() {
+        let recent: Vec<Result<(u64, Entry<u64>), &str>> = vec![
+            Ok((1, Entry::Tombstone)),
+            Ok((4, Entry::Value(40))),
+            Ok((4, Entry::Value(41))),
+        ];
+        let base: Vec<Result<(u64, Entry<u64>), &str>> = vec![
+            Ok((1, Entry::Value(10))),
+            Ok((2, Entry::Value(20))),
+            Ok((3, Entry::Value(30))),
+            Err("corrupt"),
+        ];
+        let sources = || vec![recent.clone().into_iter(), base.clone().into_iter()];
+        let mut merged = MergingIter::from_sources(sources());
+        assert_eq!(merged.next_entry(), Some(Ok((1, Entry::Tombstone))));
+        assert_eq!(merged.next_entry(), Some(Ok((2, Entry::Value(20)))));
+        assert_eq!(merged.next_entry(), Some(Err("corrupt")));
+        assert_eq!(merged.next_entry(), None);
+        // a source is read past the key it yields to find duplicates, so the
+        // error is returned in place of the entry before it
+        let merged: Vec<Result<(u64, u64), &str>> = MergingIter::from_sources(sources()).collect();
+        assert_eq!(merged, vec![Ok((2, 20)), Err("corrupt")]);
+        let merged: Vec<Result<(u64, u64), &str>> =
+            MergingIter::from_sources(vec![recent.into_iter()]).collect();
+        assert_eq!(merged, vec![Ok((4, 41))]);
+    }
+}
@@ -0,0 +1,657 @@
+//! Multi-version skip list with snapshot reads, available with the `std` feature.
+//!
+//! An [`MvccSkipList`] tags every change with a sequence number and keeps the
+//! older versions of a key, so the list can be read as it was at any earlier
+//! sequence number, either once through [`get_at`](MvccSkipList::get_at) or
+//! repeatedly through a [`Snapshot`] that may be moved to another thread.
+//!
+//! # Garbage collection
+//! Versions that neither the latest state nor a live snapshot can see are
+//! removed when their key is written again and by
+//! [`collect_garbage`](MvccSkipList::collect_garbage). The list remembers, for
+//! every key written since the last sweep, the sequence number below which its
+//! versions may be gone. Reads below it fail with [`SequenceError::Collected`]
+//! instead of returning a value that is no longer stored, while other keys stay
+//! readable at that sequence number. A sweep replaces these records with the
+//! newest of them, which then applies to every key, so that the records do not
+//! grow with the number of keys ever written. A snapshot covers every key, so it
+//! can only be taken at or after the newest of these sequence numbers.
+use crate::concurrent::{self, ConcurrentSkipList};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+// Versions of a key are ordered from the newest to the oldest.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Version<K> {
+    key: K,
+    sequence: Reverse<u64>,
+}
+
+impl<K> Version<K> {
+    fn new(key: K, sequence: u64) -> Version<K> {
+        Version {
+            key,
+            sequence: Reverse(sequence),
+        }
+    }
+}
+
+// State shared between the list and its snapshots.
+struct Shared<K, V> {
+    // values are `None` for versions written by a delete
+    versions: ConcurrentSkipList<Version<K>, Option<V>>,
+    // sequence numbers of live snapshots along with the number of snapshots at each
+    snapshots: Mutex<BTreeMap<u64, usize>>,
+}
+
+impl<K, V> Shared<K, V> {
+    fn snapshots(&self) -> MutexGuard<'_, BTreeMap<u64, usize>> {
+        self.snapshots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Skip list keeping multiple versions of every key, each tagged with the
+/// sequence number of the change that wrote it.
+///
+/// Every `insert` and `delete` creates a new version and returns its sequence
+/// number. [`get_at`](#method.get_at) and [`snapshot`](#method.snapshot) read the
+/// list as it was at an earlier sequence number. A [`Snapshot`] is an owned
+/// handle that can be moved to another thread and gives repeatable reads while
+/// the writer moves on.
+///
+/// Versions that no live snapshot can see anymore are garbage collected:
+/// writing a key removes its unreachable versions, and
+/// [`collect_garbage`](#method.collect_garbage) sweeps the whole list.
+/// Reading a key at a sequence number whose versions were collected returns
+/// [`SequenceError::Collected`].
+///
+/// # Example
+/// ```rust
+/// use subway::mvcc::MvccSkipList;
+///
+/// let mut list = MvccSkipList::new();
+/// let first = list.insert(1, "one");
+/// let snapshot = list.snapshot(first).unwrap();
+/// list.insert(1, "uno");
+/// list.delete(&1);
+/// assert_eq!(list.get(&1), None);
+/// assert_eq!(list.get_at(&1, first), Ok(Some("one")));
+/// assert_eq!(snapshot.get(&1), Some("one"));
+/// ```
+pub struct MvccSkipList<K, V> {
+    shared: Arc<Shared<K, V>>,
+    // sequence number of the latest change
+    sequence: u64,
+    // sequence number below which versions of the key may have been collected,
+    // for every key that lost versions since the last sweep
+    collected: BTreeMap<K, u64>,
+    // sequence number below which versions of any key may have been collected
+    // by the last sweep
+    collected_all: u64,
+    // newest sequence number in `collected` and `collected_all`
+    collected_any: u64,
+}
+
+impl<K, V> MvccSkipList<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    /// Create an empty list at sequence number `0`.
+    pub fn new() -> MvccSkipList<K, V> {
+        MvccSkipList {
+            shared: Arc::new(Shared {
+                versions: ConcurrentSkipList::new(),
+                snapshots: Mutex::new(BTreeMap::new()),
+            }),
+            sequence: 0,
+            collected: BTreeMap::new(),
+            collected_all: 0,
+            collected_any: 0,
+        }
+    }
+
+    /// Insert a new version of the key holding the given value.
+    ///
+    /// # Returns
+    /// The sequence number of the new version.
+    pub fn insert(&mut self, key: K, value: V) -> u64 {
+        self.write(key, Some(value))
+    }
+
+    /// Insert a new version of the key marking it as deleted.
+    ///
+    /// # Returns
+    /// The sequence number of the new version.
+    pub fn delete(&mut self, key: &K) -> u64 {
+        self.write(key.clone(), None)
+    }
+
+    /// Get the latest value associated with a key if it exists.
+    pub fn get(&self, key: &K) -> Option<V> {
+        read_at(&self.shared.versions, key, self.sequence)
+    }
+
+    /// Get the value associated with a key as of the given sequence number.
+    ///
+    /// # Returns
+    /// [`SequenceError::Ahead`] if `sequence` is newer than the latest change and
+    /// [`SequenceError::Collected`] if versions of the key it needs were collected.
+    pub fn get_at(&self, key: &K, sequence: u64) -> Result<Option<V>, SequenceError> {
+        let collected = self
+            .collected
+            .get(key)
+            .map_or(self.collected_all, |&collected| {
+                collected.max(self.collected_all)
+            });
+        self.check_sequence(sequence, collected)?;
+        Ok(read_at(&self.shared.versions, key, sequence))
+    }
+
+    /// Take a snapshot of the list as of the given sequence number.
+    /// The versions the snapshot reads are kept until it is dropped.
+    ///
+    /// # Returns
+    /// [`SequenceError::Ahead`] if `sequence` is newer than the latest change and
+    /// [`SequenceError::Collected`] if versions of any key it needs were collected.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::thread;
+    /// use subway::mvcc::MvccSkipList;
+    ///
+    /// let mut list = MvccSkipList::new();
+    /// for i in 0..100 {
+    ///     list.insert(i, i);
+    /// }
+    /// let snapshot = list.snapshot(list.sequence()).unwrap();
+    /// let export = thread::spawn(move || snapshot.iter().map(|(_, value)| value).sum::<i32>());
+    /// for i in 0..100 {
+    ///     list.insert(i, 0);
+    /// }
+    /// assert_eq!(export.join().unwrap(), 4950);
+    /// ```
+    pub fn snapshot(&self, sequence: u64) -> Result<Snapshot<K, V>, SequenceError> {
+        self.check_sequence(sequence, self.collected_any)?;
+        Ok(Snapshot::register(&self.shared, sequence))
+    }
+
+    /// Returns the sequence number of the latest change, `0` for a new list.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Returns the number of versions stored across all keys.
+    pub fn version_count(&self) -> usize {
+        self.shared.versions.len()
+    }
+
+    /// Remove every version that neither the latest state of the list nor any
+    /// live snapshot can see. Afterwards no key can be read through
+    /// [`get_at`](#method.get_at) below the oldest sequence number that lost
+    /// versions of some key.
+    ///
+    /// # Returns
+    /// The number of versions removed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let horizon = self.horizon();
+        let mut doomed = Vec::new();
+        let mut previous: Option<K> = None;
+        // whether a visible version of the current key has been passed
+        let mut covered = false;
+        for (version, value) in self.shared.versions.iter() {
+            if previous.as_ref() != Some(&version.key) {
+                previous = Some(version.key.clone());
+                covered = false;
+            }
+            if version.sequence.0 > horizon {
+                continue;
+            }
+            if covered || value.is_none() {
+                doomed.push(version);
+            }
+            covered = true;
+        }
+        let removed = self.remove_versions(doomed, horizon);
+        // every record is at most `collected_any`, so one watermark replaces them
+        self.collected_all = self.collected_any;
+        self.collected.clear();
+        removed
+    }
+
+    fn write(&mut self, key: K, value: Option<V>) -> u64 {
+        let sequence = self.sequence + 1;
+        self.shared
+            .versions
+            .insert(Version::new(key.clone(), sequence), value);
+        self.sequence = sequence;
+        self.collect_key(key);
+        sequence
+    }
+
+    // Remove the versions of the key below the newest one every reader can see.
+    // That version is removed as well if it is a delete.
+    fn collect_key(&mut self, key: K) {
+        let horizon = self.horizon();
+        let start = Version::new(key.clone(), horizon);
+        let end = Version::new(key, 0);
+        let mut versions = self.shared.versions.range(start..=end);
+        let mut doomed = Vec::new();
+        if let Some((newest, value)) = versions.next() {
+            if value.is_none() {
+                doomed.push(newest);
+            }
+        }
+        doomed.extend(versions.map(|(version, _)| version));
+        self.remove_versions(doomed, horizon);
+    }
+
+    // Remove versions given in list order, the oldest version of every key first.
+    // A delete stays in place until the older values it hides are gone, so that
+    // a snapshot reading concurrently never sees them again.
+    fn remove_versions(&mut self, doomed: Vec<Version<K>>, horizon: u64) -> usize {
+        let mut removed = 0;
+        for version in doomed.into_iter().rev() {
+            if self.shared.versions.remove(&version).is_some() {
+                self.mark_collected(&version.key, horizon);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    // Record that versions of the key older than `horizon` were removed.
+    fn mark_collected(&mut self, key: &K, horizon: u64) {
+        let collected = self.collected.entry(key.clone()).or_insert(0);
+        *collected = (*collected).max(horizon);
+        self.collected_any = self.collected_any.max(horizon);
+    }
+
+    // Sequence number of the oldest state that is still readable.
+    fn horizon(&self) -> u64 {
+        let snapshots = self.shared.snapshots();
+        match snapshots.keys().next() {
+            Some(&oldest) => oldest.min(self.sequence),
+            None => self.sequence,
+        }
+    }
+
+    fn check_sequence(&self, sequence: u64, collected: u64) -> Result<(), SequenceError> {
+        if sequence > self.sequence {
+            Err(SequenceError::Ahead {
+                latest: self.sequence,
+            })
+        } else if sequence < collected {
+            Err(SequenceError::Collected { oldest: collected })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<K, V> Default for MvccSkipList<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    fn default() -> MvccSkipList<K, V> {
+        MvccSkipList::new()
+    }
+}
+
+/// Error returned when an [`MvccSkipList`] cannot be read at a sequence number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceError {
+    /// The sequence number is newer than the latest change.
+    Ahead {
+        /// Sequence number of the latest change.
+        latest: u64,
+    },
+    /// Versions the read needs have been garbage collected.
+    Collected {
+        /// Oldest sequence number that can still be read.
+        oldest: u64,
+    },
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequenceError::Ahead { latest } => {
+                write!(
+                    f,
+                    "sequence number is newer than the latest change {}",
+                    latest
+                )
+            }
+            SequenceError::Collected { oldest } => write!(
+                f,
+                "versions older than sequence number {} have been collected",
+                oldest
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SequenceError {}
+
+// Value of the newest version of the key written at or before `sequence`.
+fn read_at<K, V>(
+    versions: &ConcurrentSkipList<Version<K>, Option<V>>,
+    key: &K,
+    sequence: u64,
+) -> Option<V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    let start = Version::new(key.clone(), sequence);
+    let end = Version::new(key.clone(), 0);
+    versions.range(start..=end).next()?.1
+}
+
+/// Read-only view of an [`MvccSkipList`] as of a sequence number, created by
+/// [`MvccSkipList::snapshot`].
+///
+/// Reads are repeatable: changes made after the sequence number are never
+/// visible. Cloning a snapshot is cheap.
+pub struct Snapshot<K, V> {
+    shared: Arc<Shared<K, V>>,
+    sequence: u64,
+}
+
+impl<K, V> Snapshot<K, V> {
+    fn register(shared: &Arc<Shared<K, V>>, sequence: u64) -> Snapshot<K, V> {
+        *shared.snapshots().entry(sequence).or_insert(0) += 1;
+        Snapshot {
+            shared: Arc::clone(shared),
+            sequence,
+        }
+    }
+}
+
+impl<K, V> Snapshot<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    /// Get the value associated with a key if it exists.
+    pub fn get(&self, key: &K) -> Option<V> {
+        read_at(&self.shared.versions, key, self.sequence)
+    }
+
+    /// Iterate over the entries whose keys fall in the given range.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::mvcc::MvccSkipList;
+    ///
+    /// let mut list = MvccSkipList::new();
+    /// for i in 0..10 {
+    ///     list.insert(i, i * 10);
+    /// }
+    /// let snapshot = list.snapshot(list.sequence()).unwrap();
+    /// list.delete(&4);
+    /// let values: Vec<i32> = snapshot.range(3..6).map(|(_, value)| value).collect();
+    /// assert_eq!(values, vec![30, 40, 50]);
+    /// ```
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<K, V> {
+        // the newest version of a key comes first, the oldest last
+        let start = match range.start_bound() {
+            Bound::Included(key) => Bound::Included(Version::new(key.clone(), u64::MAX)),
+            Bound::Excluded(key) => Bound::Excluded(Version::new(key.clone(), 0)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => Bound::Included(Version::new(key.clone(), 0)),
+            Bound::Excluded(key) => Bound::Excluded(Version::new(key.clone(), u64::MAX)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        Range {
+            versions: self.shared.versions.range((start, end)),
+            sequence: self.sequence,
+            previous: None,
+        }
+    }
+
+    /// Iterate over all entries in key order.
+    pub fn iter(&self) -> Range<K, V> {
+        self.range(..)
+    }
+
+    /// Returns the sequence number the snapshot reads at.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+}
+
+impl<K, V> Clone for Snapshot<K, V> {
+    fn clone(&self) -> Snapshot<K, V> {
+        Snapshot::register(&self.shared, self.sequence)
+    }
+}
+
+impl<K, V> Drop for Snapshot<K, V> {
+    fn drop(&mut self) {
+        let mut snapshots = self.shared.snapshots();
+        if let Some(count) = snapshots.get_mut(&self.sequence) {
+            *count -= 1;
+            if *count == 0 {
+                snapshots.remove(&self.sequence);
+            }
+        }
+    }
+}
+
+/// Iterator over the entries of a [`Snapshot`] within a range.
+pub struct Range<K, V> {
+    versions: concurrent::Range<Version<K>, Option<V>>,
+    sequence: u64,
+    // key of the last version returned or skipped as visible
+    previous: Option<K>,
+}
+
+impl<K, V> Iterator for Range<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        for (version, value) in &mut self.versions {
+            if version.sequence.0 > self.sequence || self.previous.as_ref() == Some(&version.key) {
+                continue;
+            }
+            self.previous = Some(version.key.clone());
+            if let Some(value) = value {
+                return Some((version.key, value));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_send_sync;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send_sync::<MvccSkipList<u64, String>>();
+        assert_send_sync::<Snapshot<u64, String>>();
+        assert_send_sync::<Range<u64, String>>();
+    }
+
+    #[test]
+    fn test_reads_at_sequence() {
+        let mut list = MvccSkipList::new();
+        let snapshot = list.snapshot(0).unwrap();
+        let one = list.insert(1, "one");
+        let two = list.insert(2, "two");
+        let uno = list.insert(1, "uno");
+        let deleted = list.delete(&2);
+        assert_eq!((one, two, uno, deleted), (1, 2, 3, 4));
+        assert_eq!(list.get_at(&1, 0), Ok(None));
+        assert_eq!(list.get_at(&1, one), Ok(Some("one")));
+        assert_eq!(list.get_at(&1, two), Ok(Some("one")));
+        assert_eq!(list.get_at(&1, uno), Ok(Some("uno")));
+        assert_eq!(list.get_at(&2, uno), Ok(Some("two")));
+        assert_eq!(list.get_at(&2, deleted), Ok(None));
+        assert_eq!(list.get(&1), Some("uno"));
+        assert_eq!(
+            list.snapshot(two).unwrap().iter().collect::<Vec<_>>(),
+            vec![(1, "one"), (2, "two")]
+        );
+        assert_eq!(
+            list.snapshot(deleted).unwrap().iter().collect::<Vec<_>>(),
+            vec![(1, "uno")]
+        );
+        assert_eq!(snapshot.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_range_bounds() {
+        let mut list = MvccSkipList::new();
+        for round in 0..3 {
+            for i in 0..10 {
+                list.insert(i, i * 10 + round);
+            }
+        }
+        let snapshot = list.snapshot(list.sequence()).unwrap();
+        let keys = |range: Range<i32, i32>| range.map(|(key, _)| key).collect::<Vec<_>>();
+        assert_eq!(keys(snapshot.range(3..6)), vec![3, 4, 5]);
+        assert_eq!(keys(snapshot.range(3..=6)), vec![3, 4, 5, 6]);
+        assert_eq!(
+            keys(snapshot.range((Bound::Excluded(3), Bound::Excluded(6)))),
+            vec![4, 5]
+        );
+        assert_eq!(keys(snapshot.range(8..)), vec![8, 9]);
+        assert_eq!(snapshot.get(&7), Some(72));
+    }
+
+    #[test]
+    fn test_repeatable_reads_while_writing() {
+        let mut list = MvccSkipList::new();
+        for i in 0..1000u64 {
+            list.insert(i, i);
+        }
+        let snapshot = list.snapshot(list.sequence()).unwrap();
+        let export = {
+            let snapshot = snapshot.clone();
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    let sum: u64 = snapshot.iter().map(|(_, value)| value).sum();
+                    assert_eq!(sum, 499_500);
+                }
+            })
+        };
+        for round in 1..5 {
+            for i in 0..1000u64 {
+                if i % 3 == 0 {
+                    list.delete(&i);
+                } else {
+                    list.insert(i, i * round);
+                }
+            }
+        }
+        export.join().unwrap();
+        assert_eq!(snapshot.iter().count(), 1000);
+        assert_eq!(list.snapshot(list.sequence()).unwrap().iter().count(), 666);
+    }
+
+    #[test]
+    fn test_collects_unreachable_versions() {
+        let mut list = MvccSkipList::new();
+        for round in 0..5 {
+            for i in 0..100 {
+                list.insert(i, round);
+            }
+        }
+        // without snapshots only the latest version of every key is kept
+        assert_eq!(list.version_count(), 100);
+        let snapshot = list.snapshot(list.sequence()).unwrap();
+        for i in 0..100 {
+            list.insert(i, 5);
+        }
+        list.delete(&0);
+        assert_eq!(list.version_count(), 201);
+        assert_eq!(list.collect_garbage(), 0);
+        assert_eq!(snapshot.get(&0), Some(4));
+        drop(snapshot);
+        assert_eq!(list.collect_garbage(), 102);
+        assert_eq!(list.version_count(), 99);
+        // the sweep leaves a single watermark rather than one per key
+        assert!(list.collected.is_empty());
+        assert_eq!(
+            list.get_at(&1, list.sequence() - 1),
+            Err(SequenceError::Collected {
+                oldest: list.sequence()
+            })
+        );
+        assert_eq!(list.get(&0), None);
+        assert_eq!(list.get(&1), Some(5));
+    }
+
+    #[test]
+    fn test_repeatable_reads_while_collecting_deletes() {
+        for _ in 0..20 {
+            let mut list = MvccSkipList::new();
+            for i in 0..2000u64 {
+                list.insert(i, i);
+            }
+            let before = list.snapshot(list.sequence()).unwrap();
+            for i in 0..2000u64 {
+                list.delete(&i);
+            }
+            let after = list.snapshot(list.sequence()).unwrap();
+            let done = Arc::new(AtomicBool::new(false));
+            let reader = {
+                let done = Arc::clone(&done);
+                thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        assert_eq!(after.iter().next(), None);
+                        assert_eq!(after.get(&1999), None);
+                    }
+                })
+            };
+            drop(before);
+            assert_eq!(list.collect_garbage(), 4000);
+            done.store(true, Ordering::Relaxed);
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rejects_collected_sequence() {
+        let mut list = MvccSkipList::new();
+        let first = list.insert(1, "one");
+        list.insert(2, "two");
+        let uno = list.insert(1, "uno");
+        assert_eq!(
+            list.get_at(&1, first),
+            Err(SequenceError::Collected { oldest: uno })
+        );
+        assert_eq!(list.get_at(&1, uno), Ok(Some("uno")));
+        // versions of other keys are still there
+        assert_eq!(list.get_at(&2, first), Ok(None));
+        assert_eq!(list.get_at(&2, uno - 1), Ok(Some("two")));
+        assert!(matches!(
+            list.snapshot(first),
+            Err(SequenceError::Collected { oldest }) if oldest == uno
+        ));
+        assert_eq!(
+            list.get_at(&1, uno + 1),
+            Err(SequenceError::Ahead { latest: uno })
+        );
+        assert!(list.snapshot(uno + 1).is_err());
+    }
+}
@@ -0,0 +1,458 @@
+use crate::rng::{random_seed, tower_height, SplitMix64};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+use rand::RngCore;
+
+/// Probability with which an entry is promoted to the next level.
+const PROBABILITY: f64 = 0.25;
+/// Upper bound on the number of levels.
+const MAX_LEVEL: usize = 32;
+
+type Link<K, V> = Arc<Node<K, V>>;
+
+// Segment of one level of the skip list.
+// A node at level `l` holds the consecutive entries whose towers are exactly
+// `l + 1` high, and between every two of them the segment of level `l - 1`
+// that their towers enclose. Nodes are shared between versions and copied
+// before they are changed.
+#[derive(Clone)]
+struct Node<K, V> {
+    entries: Vec<(K, V)>,
+    // empty in the bottom level, otherwise one more than the entries
+    children: Vec<Link<K, V>>,
+}
+
+impl<K, V> Node<K, V> {
+    fn empty() -> Node<K, V> {
+        Node {
+            entries: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+// Take the node out of the `Arc`, copying it only if another version uses it.
+fn unwrap_or_clone<K: Clone, V: Clone>(node: Link<K, V>) -> Node<K, V> {
+    Arc::try_unwrap(node).unwrap_or_else(|node| (*node).clone())
+}
+
+/// Immutable skip list whose updates return a new version of the list.
+///
+/// A new version copies only the nodes on the path to the changed key and
+/// shares every other node with the version it was derived from, so old
+/// versions stay valid and cloning a list is `O(1)`.
+/// Versions can be shared between threads.
+/// Keys are unique, inserting an existing key replaces its value.
+///
+/// # Example
+/// ```rust
+/// use subway::persistent::PersistentSkipList;
+///
+/// let empty = PersistentSkipList::new();
+/// let first = empty.insert("timeout", 30);
+/// let second = first.insert("timeout", 60).insert("retries", 3);
+/// assert_eq!(first.get(&"timeout"), Some(&30));
+/// assert_eq!(second.get(&"timeout"), Some(&60));
+/// assert_eq!(second.remove(&"retries").len(), 1);
+/// assert!(empty.is_empty());
+/// ```
+pub struct PersistentSkipList<K, V> {
+    root: Link<K, V>,
+    // level of the root, entries in the root have towers `level + 1` high
+    level: usize,
+    size: usize,
+    // carried along so that every version derives heights from its own history
+    rng: SplitMix64,
+}
+
+impl<K, V> PersistentSkipList<K, V>
+where
+    K: Ord,
+{
+    /// Create an empty list.
+    pub fn new() -> PersistentSkipList<K, V> {
+        PersistentSkipList {
+            root: Arc::new(Node::empty()),
+            level: 0,
+            size: 0,
+            rng: SplitMix64::new(random_seed()),
+        }
+    }
+
+    /// Get the value associated with a key if it exists.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut node = &self.root;
+        loop {
+            match node.entries.binary_search_by(|entry| entry.0.cmp(key)) {
+                Ok(index) => return Some(&node.entries[index].1),
+                Err(index) => node = node.children.get(index)?,
+            }
+        }
+    }
+
+    /// Iterate over the entries whose keys fall in the given range.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::persistent::PersistentSkipList;
+    ///
+    /// let list = (0..10).fold(PersistentSkipList::new(), |list, i| list.insert(i, i * 10));
+    /// let values: Vec<&i32> = list.range(3..6).map(|(_, value)| value).collect();
+    /// assert_eq!(values, vec![&30, &40, &50]);
+    /// ```
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V>
+    where
+        K: Clone,
+    {
+        let mut stack = Vec::new();
+        let mut node = &self.root;
+        loop {
+            let index = node
+                .entries
+                .partition_point(|entry| match range.start_bound() {
+                    Bound::Included(start) => &entry.0 < start,
+                    Bound::Excluded(start) => &entry.0 <= start,
+                    Bound::Unbounded => false,
+                });
+            stack.push((&**node, index));
+            match node.children.get(index) {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        Iter {
+            stack,
+            end: range.end_bound().cloned(),
+        }
+    }
+
+    /// Iterate over all entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V>
+    where
+        K: Clone,
+    {
+        self.range(..)
+    }
+
+    /// Returns the number of entries in the list.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Returns whether list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+impl<K, V> PersistentSkipList<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    /// Returns a new version of the list holding the given key and value.
+    /// The list itself is left unchanged.
+    pub fn insert(&self, key: K, value: V) -> PersistentSkipList<K, V> {
+        let mut list = self.clone();
+        list.insert_in_place(key, value);
+        list
+    }
+
+    /// Returns a new version of the list without the given key.
+    /// The list itself is left unchanged.
+    pub fn remove(&self, key: &K) -> PersistentSkipList<K, V> {
+        let mut list = self.clone();
+        list.remove_in_place(key);
+        list
+    }
+
+    // Update this version, copying the nodes it shares with other versions.
+    // Returns the value previously associated with the key.
+    pub(crate) fn insert_in_place(&mut self, key: K, value: V) -> Option<V> {
+        if self.get(&key).is_some() {
+            return Some(PersistentSkipList::replace(&mut self.root, &key, value));
+        }
+        let height = tower_height(self.rng.next_u64(), PROBABILITY, MAX_LEVEL);
+        while self.level + 1 < height {
+            let root = mem::replace(&mut self.root, Arc::new(Node::empty()));
+            self.root = Arc::new(Node {
+                entries: Vec::new(),
+                children: vec![root],
+            });
+            self.level += 1;
+        }
+        PersistentSkipList::insert_at(&mut self.root, self.level, key, value, height);
+        self.size += 1;
+        None
+    }
+
+    pub(crate) fn remove_in_place(&mut self, key: &K) -> Option<V> {
+        self.get(key)?;
+        let value = PersistentSkipList::remove_from(&mut self.root, key);
+        self.size -= 1;
+        while self.level > 0 && self.root.entries.is_empty() {
+            self.root = Arc::clone(&self.root.children[0]);
+            self.level -= 1;
+        }
+        Some(value)
+    }
+
+    // Swap the value of an existing key, copying the path to it.
+    fn replace(node: &mut Link<K, V>, key: &K, value: V) -> V {
+        let node = Arc::make_mut(node);
+        match node.entries.binary_search_by(|entry| entry.0.cmp(key)) {
+            Ok(index) => mem::replace(&mut node.entries[index].1, value),
+            Err(index) => PersistentSkipList::replace(&mut node.children[index], key, value),
+        }
+    }
+
+    // Descend to the level of the new tower and split the segments below it
+    // around the key.
+    fn insert_at(node: &mut Link<K, V>, level: usize, key: K, value: V, height: usize) {
+        let node = Arc::make_mut(node);
+        let index = node.entries.partition_point(|entry| entry.0 < key);
+        if level + 1 > height {
+            let child = &mut node.children[index];
+            return PersistentSkipList::insert_at(child, level - 1, key, value, height);
+        }
+        if !node.is_leaf() {
+            let child = mem::replace(&mut node.children[index], Arc::new(Node::empty()));
+            let (left, right) = PersistentSkipList::split(child, &key);
+            node.children[index] = left;
+            node.children.insert(index + 1, right);
+        }
+        node.entries.insert(index, (key, value));
+    }
+
+    // Split a segment into the entries before and after a key it does not hold.
+    fn split(node: Link<K, V>, key: &K) -> (Link<K, V>, Link<K, V>) {
+        let mut left = unwrap_or_clone(node);
+        let index = left.entries.partition_point(|entry| &entry.0 < key);
+        let mut right = Node {
+            entries: left.entries.split_off(index),
+            children: Vec::new(),
+        };
+        if !left.is_leaf() {
+            right.children = left.children.split_off(index + 1);
+            let child = left.children.pop().expect("internal node has children");
+            let (child_left, child_right) = PersistentSkipList::split(child, key);
+            left.children.push(child_left);
+            right.children.insert(0, child_right);
+        }
+        (Arc::new(left), Arc::new(right))
+    }
+
+    // Remove an existing key, joining the segments its tower separated.
+    fn remove_from(node: &mut Link<K, V>, key: &K) -> V {
+        let node = Arc::make_mut(node);
+        match node.entries.binary_search_by(|entry| entry.0.cmp(key)) {
+            Ok(index) => {
+                let (_, value) = node.entries.remove(index);
+                if !node.is_leaf() {
+                    let right = node.children.remove(index + 1);
+                    let left = mem::replace(&mut node.children[index], Arc::new(Node::empty()));
+                    node.children[index] = PersistentSkipList::merge(left, right);
+                }
+                value
+            }
+            Err(index) => PersistentSkipList::remove_from(&mut node.children[index], key),
+        }
+    }
+
+    // Join two adjacent segments of the same level.
+    fn merge(left: Link<K, V>, right: Link<K, V>) -> Link<K, V> {
+        let mut left = unwrap_or_clone(left);
+        let right = unwrap_or_clone(right);
+        let mut children = right.children.into_iter();
+        if let (Some(last), Some(first)) = (left.children.pop(), children.next()) {
+            left.children.push(PersistentSkipList::merge(last, first));
+        }
+        left.entries.extend(right.entries);
+        left.children.extend(children);
+        Arc::new(left)
+    }
+}
+
+impl<K, V> Clone for PersistentSkipList<K, V> {
+    fn clone(&self) -> PersistentSkipList<K, V> {
+        PersistentSkipList {
+            root: Arc::clone(&self.root),
+            level: self.level,
+            size: self.size,
+            rng: self.rng.clone(),
+        }
+    }
+}
+
+impl<K, V> Default for PersistentSkipList<K, V>
+where
+    K: Ord,
+{
+    fn default() -> PersistentSkipList<K, V> {
+        PersistentSkipList::new()
+    }
+}
+
+/// Iterator over the entries of a [`PersistentSkipList`] in key order.
+pub struct Iter<'a, K, V> {
+    // nodes on the path to the next entry, with the position of that entry
+    // in each node; the child before that position has been visited
+    stack: Vec<(&'a Node<K, V>, usize)>,
+    end: Bound<K>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, index) = self.stack.last_mut()?;
+            let node: &'a Node<K, V> = node;
+            let (key, value) = match node.entries.get(*index) {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            *index += 1;
+            let within = match &self.end {
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+                Bound::Unbounded => true,
+            };
+            if !within {
+                self.stack.clear();
+                return None;
+            }
+            // visit the segment after the entry before returning to this node
+            let mut child = node.children.get(*index);
+            while let Some(next) = child {
+                self.stack.push((next, 0));
+                child = next.children.first();
+            }
+            return Some((key, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_send_sync;
+    use std::collections::BTreeMap;
+
+    // Check the shape of every node and that the entries are sorted.
+    fn assert_valid(list: &PersistentSkipList<i32, usize>) {
+        fn check(node: &Node<i32, usize>, level: usize) -> usize {
+            if level == 0 {
+                assert!(node.is_leaf());
+                return node.entries.len();
+            }
+            assert_eq!(node.children.len(), node.entries.len() + 1);
+            let below: usize = node
+                .children
+                .iter()
+                .map(|child| check(child, level - 1))
+                .sum();
+            node.entries.len() + below
+        }
+        assert_eq!(check(&list.root, list.level), list.size);
+        let keys: Vec<&i32> = list.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys.len(), list.size);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send_sync::<PersistentSkipList<u64, String>>();
+        assert_send_sync::<Iter<'static, u64, String>>();
+    }
+
+    #[test]
+    fn test_versions_are_independent() {
+        let mut versions = vec![PersistentSkipList::new()];
+        for i in 0..100 {
+            let next = versions[i].insert(i as i32, i);
+            versions.push(next);
+        }
+        for (size, version) in versions.iter().enumerate() {
+            assert_valid(version);
+            assert_eq!(version.len(), size);
+            assert_eq!(version.get(&(size as i32)), None);
+        }
+        let last = versions.last().unwrap();
+        let removed = last.remove(&50);
+        assert_eq!(removed.get(&50), None);
+        assert_eq!(last.get(&50), Some(&50));
+        assert_eq!(removed.remove(&50).len(), 99);
+    }
+
+    #[test]
+    fn test_updates_share_nodes() {
+        let list = (0..1000).fold(PersistentSkipList::new(), |list, i| list.insert(i, 0));
+        let updated = list.insert(500, 1);
+        assert_eq!(updated.len(), 1000);
+        assert_eq!(updated.get(&500), Some(&1));
+        assert_eq!(list.get(&500), Some(&0));
+        // only the path to the updated key is copied
+        assert!(list.root.children.len() > 1);
+        let shared = list
+            .root
+            .children
+            .iter()
+            .zip(updated.root.children.iter())
+            .filter(|(old, new)| Arc::ptr_eq(old, new))
+            .count();
+        assert!(shared >= list.root.children.len() - 1);
+    }
+
+    #[test]
+    fn test_range() {
+        let list = (0..500).fold(PersistentSkipList::new(), |list, i| list.insert(i * 2, i));
+        let keys: Vec<i32> = list.range(11..=20).map(|(key, _)| *key).collect();
+        assert_eq!(keys, vec![12, 14, 16, 18, 20]);
+        let keys: Vec<i32> = list
+            .range((Bound::Excluded(990), Bound::Unbounded))
+            .map(|(key, _)| *key)
+            .collect();
+        assert_eq!(keys, vec![992, 994, 996, 998]);
+        assert_eq!(list.range(2000..).count(), 0);
+    }
+
+    #[test]
+    fn test_matches_model() {
+        let mut rng = SplitMix64::new(11);
+        let mut list = PersistentSkipList::new();
+        let mut model = BTreeMap::new();
+        let mut history = Vec::new();
+        for step in 0..3000 {
+            let key = (rng.next_u64() % 200) as i32;
+            if rng.next_u64().is_multiple_of(3) {
+                assert_eq!(list.remove_in_place(&key), model.remove(&key));
+            } else {
+                assert_eq!(list.insert_in_place(key, step), model.insert(key, step));
+            }
+            if step % 300 == 0 {
+                history.push((list.clone(), model.clone()));
+            }
+        }
+        assert_valid(&list);
+        for (version, model) in history {
+            assert_valid(&version);
+            let entries: Vec<(i32, usize)> =
+                version.iter().map(|(key, value)| (*key, *value)).collect();
+            assert_eq!(entries, model.into_iter().collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use rand::{Error, RngCore};
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+#[cfg(not(feature = "std"))]
+const FALLBACK_SEED: u64 = 0x5eed_5eed_5eed_5eed;
+
+// Small and fast pseudo random number generator (SplitMix64).
+// Used for seeded lists and as the default source of tower heights.
+// It is not cryptographically secure.
+#[derive(Clone)]
+pub(crate) struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+}
+
+impl RngCore for SplitMix64 {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// Seed for generators that are not seeded by the caller.
+// Without `std` there is no source of entropy, so such generators all start
+// from the same fixed seed.
+pub(crate) fn random_seed() -> u64 {
+    #[cfg(feature = "std")]
+    {
+        rand::thread_rng().next_u64()
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        FALLBACK_SEED
+    }
+}
+
+// SplitMix64 whose state can be advanced through a shared reference,
+// so that many threads can draw tower heights without locking.
+#[cfg(feature = "std")]
+pub(crate) struct AtomicSplitMix64 {
+    state: AtomicU64,
+}
+
+#[cfg(feature = "std")]
+impl AtomicSplitMix64 {
+    pub(crate) fn new(seed: u64) -> AtomicSplitMix64 {
+        AtomicSplitMix64 {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    pub(crate) fn next_u64(&self) -> u64 {
+        let previous = self.state.fetch_add(GOLDEN_GAMMA, Ordering::Relaxed);
+        mix(previous.wrapping_add(GOLDEN_GAMMA))
+    }
+}
+
+fn mix(state: u64) -> u64 {
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Derive the height of a tower from a single random word.
+// The tower is promoted while the top 53 bits of the word fall below
+// `p * 2^53`, rehashing the word before every further promotion,
+// so that P(height > k) = p^k without floating point logarithms.
+pub(crate) fn tower_height(word: u64, probability: f64, max_level: usize) -> usize {
+    let threshold = (probability * (1u64 << 53) as f64) as u64;
+    let mut word = word;
+    let mut height = 1;
+    while height < max_level && (word >> 11) < threshold {
+        word = mix(word);
+        height += 1;
+    }
+    height
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_mix_is_deterministic() {
+        let mut first = SplitMix64::new(42);
+        let mut second = SplitMix64::new(42);
+        for _ in 0..100 {
+            assert_eq!(first.next_u64(), second.next_u64());
+        }
+        let mut other = SplitMix64::new(43);
+        assert_ne!(SplitMix64::new(42).next_u64(), other.next_u64());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_atomic_split_mix_matches_sequential() {
+        let mut sequential = SplitMix64::new(42);
+        let shared = AtomicSplitMix64::new(42);
+        for _ in 0..100 {
+            assert_eq!(sequential.next_u64(), shared.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_fill_bytes() {
+        let mut rng = SplitMix64::new(7);
+        let mut bytes = [0u8; 13];
+        rng.fill_bytes(&mut bytes);
+        assert!(bytes.iter().any(|byte| *byte != 0));
+    }
+
+    #[test]
+    fn test_tower_height_bounds() {
+        assert_eq!(tower_height(u64::MAX, 0.5, 32), 1);
+        assert_eq!(tower_height(0, 0.5, 32), 32);
+        assert_eq!(tower_height(0, 0.5, 4), 4);
+    }
+
+    #[test]
+    fn test_tower_height_distribution() {
+        let mut rng = SplitMix64::new(1);
+        let samples = 100_000;
+        let mut promoted = 0;
+        for _ in 0..samples {
+            if tower_height(rng.next_u64(), 0.25, 32) > 1 {
+                promoted += 1;
+            }
+        }
+        let ratio = promoted as f64 / samples as f64;
+        assert!((ratio - 0.25).abs() < 0.01);
+    }
+}
@@ -0,0 +1,222 @@
+//! [serde](https://serde.rs) support for [`SkipList`], enabled by the `serde` feature.
+//!
+//! A list is serialized as a sequence of `(key, value)` pairs in key order
+//! and deserialized by building its levels bottom up in `O(n)` instead of
+//! inserting entries one at a time. Entries that are not sorted by key are
+//! sorted first, keeping the order of equal keys.
+//!
+//! Tower heights are drawn afresh on deserialization. Use the [`heights`]
+//! module to keep the exact shape of a list across a round trip.
+//!
+//! # Example
+//! ```rust
+//! use subway::skiplist::SkipList;
+//!
+//! let mut list = SkipList::new();
+//! list.insert(2, "two".to_owned());
+//! list.insert(1, "one".to_owned());
+//! let json = serde_json::to_string(&list).unwrap();
+//! assert_eq!(json, r#"[[1,"one"],[2,"two"]]"#);
+//! let restored: SkipList<i32, String> = serde_json::from_str(&json).unwrap();
+//! assert_eq!(restored.collect(), list.collect());
+//! ```
+use crate::skiplist::SkipList;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+// Upper bound on the capacity reserved from an untrusted length hint.
+const MAX_PREALLOCATED_ENTRIES: usize = 4096;
+
+impl<K, V> Serialize for SkipList<K, V>
+where
+    K: Ord + Clone + Serialize,
+    V: Clone + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        self.visit_entries(|key, value, _| seq.serialize_element(&(key, value)))?;
+        seq.end()
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for SkipList<K, V>
+where
+    K: Ord + Clone + Deserialize<'de>,
+    V: Clone + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut entries: Vec<(K, V)> = deserializer.deserialize_seq(EntriesVisitor::new())?;
+        if !entries.windows(2).all(|pair| pair[0].0 <= pair[1].0) {
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        let mut list = SkipList::new();
+        list.load_sorted(entries, None);
+        Ok(list)
+    }
+}
+
+// Collects the elements of a sequence into a vector.
+struct EntriesVisitor<T> {
+    marker: PhantomData<T>,
+}
+
+impl<T> EntriesVisitor<T> {
+    fn new() -> EntriesVisitor<T> {
+        EntriesVisitor {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for EntriesVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a sequence of skip list entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+        let capacity = seq.size_hint().unwrap_or(0).min(MAX_PREALLOCATED_ENTRIES);
+        let mut entries = Vec::with_capacity(capacity);
+        while let Some(entry) = seq.next_element()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+/// Serialize a [`SkipList`] together with the height of every tower, so that
+/// a deserialized list has exactly the same shape as the original.
+///
+/// Entries are written as `(key, value, height)` triples. Use the module with
+/// serde's `with` attribute.
+///
+/// # Example
+/// ```rust
+/// use serde::{Deserialize, Serialize};
+/// use subway::skiplist::SkipList;
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Index {
+///     #[serde(with = "subway::serialization::heights")]
+///     entries: SkipList<u64, String>,
+/// }
+/// ```
+pub mod heights {
+    use super::EntriesVisitor;
+    use crate::skiplist::SkipList;
+    use alloc::vec::Vec;
+    use serde::de::{Deserialize, Deserializer};
+    use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+    /// Serialize the entries of the list along with their tower heights.
+    pub fn serialize<K, V, S>(list: &SkipList<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + Clone + Serialize,
+        V: Clone + Serialize,
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(list.len()))?;
+        list.visit_entries(|key, value, height| seq.serialize_element(&(key, value, height)))?;
+        seq.end()
+    }
+
+    /// Deserialize a list written by [`serialize`], restoring its tower heights.
+    /// Heights are capped at the maximum level of a default list.
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<SkipList<K, V>, D::Error>
+    where
+        K: Ord + Clone + Deserialize<'de>,
+        V: Clone + Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let mut entries: Vec<(K, V, usize)> = deserializer.deserialize_seq(EntriesVisitor::new())?;
+        if !entries.windows(2).all(|pair| pair[0].0 <= pair[1].0) {
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        let mut heights = Vec::with_capacity(entries.len());
+        let entries = entries
+            .into_iter()
+            .map(|(key, value, height)| {
+                heights.push(height);
+                (key, value)
+            })
+            .collect();
+        let mut list = SkipList::new();
+        list.load_sorted(entries, Some(heights));
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skiplist::SkipListBuilder;
+
+    fn sample() -> SkipList<u32, String> {
+        let mut list = SkipListBuilder::new().seed(11).build();
+        for i in (0..500).rev() {
+            list.insert(i, i.to_string());
+        }
+        list.insert(7, "seven".to_owned());
+        list
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let list = sample();
+        let json = serde_json::to_string(&list).unwrap();
+        let restored: SkipList<u32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.collect(), list.collect());
+        assert_eq!(restored.len(), 501);
+    }
+
+    #[test]
+    fn test_bincode_round_trip() {
+        let list = sample();
+        let bytes = bincode::serialize(&list).unwrap();
+        let mut restored: SkipList<u32, String> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.collect(), list.collect());
+        assert_eq!(restored.get(&499), Some("499".to_owned()));
+        // the restored list keeps working as usual
+        restored.insert(1000, "1000".to_owned());
+        restored.delete(&0);
+        assert_eq!(restored.len(), 501);
+    }
+
+    #[test]
+    fn test_deserialize_unsorted() {
+        let json = r#"[[3,"c"],[1,"a"],[2,"b"],[1,"z"]]"#;
+        let mut list: SkipList<u32, String> = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            list.collect(),
+            vec![
+                (1, "a".to_owned()),
+                (1, "z".to_owned()),
+                (2, "b".to_owned()),
+                (3, "c".to_owned())
+            ]
+        );
+        assert_eq!(list.get(&2), Some("b".to_owned()));
+        let empty: SkipList<u32, String> = serde_json::from_str("[]").unwrap();
+        assert!(empty.is_empty());
+        assert!(serde_json::from_str::<SkipList<u32, String>>(r#"{"a":1}"#).is_err());
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Index {
+        #[serde(with = "heights")]
+        entries: SkipList<u32, String>,
+    }
+
+    #[test]
+    fn test_heights_round_trip() {
+        let index = Index { entries: sample() };
+        let bytes = bincode::serialize(&index).unwrap();
+        let restored: Index = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.entries.collect(), index.entries.collect());
+        assert_eq!(restored.entries.stats(), index.entries.stats());
+    }
+}
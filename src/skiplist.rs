@@ -1,13 +1,33 @@
-use rand::Rng;
-use std::cell::RefCell;
-use std::clone::Clone;
-use std::cmp::{Ord, Ordering};
-use std::fmt::Display;
-use std::option::Option;
-use std::rc::{Rc, Weak};
+use crate::heap_size::HeapSize;
+use crate::rng::random_seed;
+use crate::rng::{tower_height, SplitMix64};
+use alloc::boxed::Box;
+use alloc::rc::{Rc, Weak};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::clone::Clone;
+use core::cmp::{Ord, Ordering};
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ops::{Bound, RangeBounds};
+use core::option::Option;
+use core::ptr;
+use rand::RngCore;
 
 type Link<K, V> = Option<Rc<RefCell<Node<K, V>>>>;
 type WeakLink<K, V> = Option<Weak<RefCell<Node<K, V>>>>;
+// Allocation of a node kept in the pool without an entry.
+type Slot<K, V> = Rc<MaybeUninit<RefCell<Node<K, V>>>>;
+// Level and node from which a search walks right and then down.
+type SearchStart<K, V> = (usize, Rc<RefCell<Node<K, V>>>);
+
+/// Probability with which a node is promoted to the next level.
+const DEFAULT_PROBABILITY: f64 = 0.5;
+/// Upper bound on the number of levels a list may grow to.
+const DEFAULT_MAX_LEVEL: usize = 32;
+/// Number of keys searched to estimate the average search path length.
+const STATS_SAMPLE_SIZE: usize = 100;
 
 struct Node<K, V> {
     key: K,
@@ -56,8 +76,8 @@ where
         }
     }
 
-    fn iter(&self) -> Iter<K, V> {
-        Iter {
+    fn iter(&self) -> Nodes<K, V> {
+        Nodes {
             next: self.head.as_ref().map(Rc::clone),
         }
     }
@@ -69,14 +89,13 @@ where
     //                    |
     //    bisection point for key `3`
     fn bisect(&mut self, key: &K) -> Link<K, V> {
-        let maybe_marker = self.iter().find(|node_ref| {
-            return match node_ref.borrow().cmp(key) {
+        let maybe_marker = self
+            .iter()
+            .find(|node_ref| match node_ref.borrow().cmp(key) {
                 Ordering::Greater => true,
                 Ordering::Less | Ordering::Equal => false,
-            };
-        });
-        if maybe_marker.is_some() {
-            let marker = maybe_marker.unwrap();
+            });
+        if let Some(marker) = maybe_marker {
             return marker.borrow().left.as_ref().and_then(Weak::upgrade);
         }
         self.iter().last()
@@ -94,8 +113,7 @@ where
         let mut maybe_current = Some(Rc::clone(node));
         let mut prev: Link<K, V> = node.borrow().left.as_ref().and_then(Weak::upgrade);
         let mut output = None;
-        while maybe_current.is_some() {
-            let current = maybe_current.take().unwrap();
+        while let Some(current) = maybe_current.take() {
             prev = Some(Rc::clone(&current));
             match current.borrow().cmp(target) {
                 Ordering::Less => {
@@ -116,15 +134,14 @@ where
         if output.is_some() {
             return output;
         }
-        return prev;
+        prev
     }
 
-    fn insert(&mut self, key: K, value: V) -> Rc<RefCell<Node<K, V>>> {
+    fn insert(&mut self, new_node: Rc<RefCell<Node<K, V>>>) -> Rc<RefCell<Node<K, V>>> {
         let mut head: Link<K, V> = self.head.as_ref().map(Rc::clone);
         let mut maybe_prev_node = Option::None;
-        while head.is_some() {
-            let node = head.take().unwrap();
-            match node.borrow().cmp(&key) {
+        while let Some(node) = head.take() {
+            match node.borrow().cmp(&new_node.borrow().key) {
                 Ordering::Less | Ordering::Equal => {
                     maybe_prev_node = Some(Rc::clone(&node));
                     head = node.borrow().right.as_ref().map(Rc::clone);
@@ -134,19 +151,17 @@ where
                 }
             };
         }
-        return match maybe_prev_node {
+        match maybe_prev_node {
             // insert at head
             None => {
                 let maybe_prev_head_ref: Option<Rc<RefCell<Node<K, V>>>> =
                     self.head.as_ref().map(Rc::clone);
-                if maybe_prev_head_ref.is_some() {
-                    let prev_head_ref = maybe_prev_head_ref.unwrap();
-                    let new_head = Rc::new(RefCell::new(Node::new(key, value)));
-                    new_head.borrow_mut().right = self.head.take();
-                    self.head = Some(new_head);
+                if let Some(prev_head_ref) = maybe_prev_head_ref {
+                    new_node.borrow_mut().right = self.head.take();
+                    self.head = Some(new_node);
                     prev_head_ref.borrow_mut().left = self.head.as_ref().map(Rc::downgrade);
                 } else {
-                    self.head = Some(Rc::new(RefCell::new(Node::new(key, value))));
+                    self.head = Some(new_node);
                 }
                 self.size += 1;
                 Rc::clone(self.head.as_ref().unwrap())
@@ -154,10 +169,8 @@ where
             Some(prev_node) => {
                 let maybe_next_node: Option<Rc<RefCell<Node<K, V>>>> =
                     prev_node.borrow().right.as_ref().map(Rc::clone);
-                let new_node = Rc::new(RefCell::new(Node::new(key, value)));
-                if maybe_next_node.is_some() {
+                if let Some(next_node) = maybe_next_node {
                     // handle insert in the middle
-                    let next_node = maybe_next_node.unwrap();
                     next_node.borrow_mut().left = Some(Rc::downgrade(&new_node));
                     new_node.borrow_mut().right = prev_node.borrow_mut().right.take();
                     new_node.borrow_mut().left = Some(Rc::downgrade(&prev_node));
@@ -171,7 +184,7 @@ where
                 }
                 Rc::clone(prev_node.borrow().right.as_ref().unwrap())
             }
-        };
+        }
     }
 
     // Insert after the supplied node.
@@ -179,15 +192,12 @@ where
     // It is up to the caller to ensure that the sorted order is maintained.
     fn insert_after(
         &mut self,
-        key: K,
-        value: V,
+        node: Rc<RefCell<Node<K, V>>>,
         after: Rc<RefCell<Node<K, V>>>,
     ) -> Rc<RefCell<Node<K, V>>> {
-        let node = Rc::new(RefCell::new(Node::new(key, value)));
         let maybe_next_node = after.borrow_mut().right.take();
         node.borrow_mut().left = Some(Rc::downgrade(&after));
-        if maybe_next_node.is_some() {
-            let next_node = maybe_next_node.unwrap();
+        if let Some(next_node) = maybe_next_node {
             next_node.borrow_mut().left = Some(Rc::downgrade(&node));
             node.borrow_mut().right = Some(next_node);
         }
@@ -196,46 +206,132 @@ where
         Rc::clone(after.borrow().right.as_ref().unwrap())
     }
 
-    fn delete(&mut self, key: &K) {
-        let maybe_node = self.iter().find(|node_ref| {
-            return match node_ref.borrow().cmp(key) {
-                Ordering::Equal => true,
-                Ordering::Less | Ordering::Greater => false,
-            };
-        });
-        if maybe_node.is_some() {
-            let to_delete = maybe_node.as_ref().unwrap();
-            let maybe_prev_node = to_delete.borrow().left.as_ref().and_then(Weak::upgrade);
-            if maybe_prev_node.is_some() {
-                let prev_node: Rc<RefCell<Node<K, V>>> = maybe_prev_node.unwrap();
-                let maybe_new_next: Option<Rc<RefCell<Node<K, V>>>> =
-                    to_delete.borrow().right.as_ref().map(Rc::clone);
-                if maybe_new_next.is_some() {
-                    let new_next = maybe_new_next.unwrap();
-                    new_next.borrow_mut().left = Some(Rc::downgrade(&prev_node));
-                }
-                prev_node.borrow_mut().right = to_delete.borrow_mut().right.take();
-            } else {
-                // handle deleting head
-                self.head = to_delete.borrow_mut().right.take();
-                to_delete.borrow_mut().left = None;
+    // Insert a node in front of the current head.
+    // Unlike `insert` this also places the node before nodes with an equal key.
+    fn push_front(&mut self, node: Rc<RefCell<Node<K, V>>>) -> Rc<RefCell<Node<K, V>>> {
+        if let Some(prev_head) = self.head.take() {
+            prev_head.borrow_mut().left = Some(Rc::downgrade(&node));
+            node.borrow_mut().right = Some(prev_head);
+        }
+        self.head = Some(Rc::clone(&node));
+        self.size += 1;
+        node
+    }
+
+    // Remove the supplied node from this level.
+    // The node is detached from its neighbours and from the node below it.
+    fn unlink(&mut self, node: &Rc<RefCell<Node<K, V>>>) {
+        let maybe_prev_node = node.borrow().left.as_ref().and_then(Weak::upgrade);
+        let maybe_next_node = node.borrow_mut().right.take();
+        if let Some(next_node) = maybe_next_node.as_ref() {
+            next_node.borrow_mut().left = maybe_prev_node.as_ref().map(Rc::downgrade);
+        }
+        match maybe_prev_node {
+            Some(prev_node) => prev_node.borrow_mut().right = maybe_next_node,
+            // handle deleting head
+            None => self.head = maybe_next_node,
+        }
+        let mut node_ref = node.borrow_mut();
+        node_ref.left = None;
+        node_ref.up = None;
+        node_ref.down = None;
+        self.size -= 1;
+    }
+}
+
+impl<K, V> Level<K, V> {
+    // Unlink the nodes of the level one at a time.
+    // Dropping the head directly would recurse through every `right` link.
+    fn clear(&mut self) {
+        let mut maybe_node = self.head.take();
+        while let Some(node) = maybe_node {
+            maybe_node = node.borrow_mut().right.take();
+            node.borrow_mut().down = None;
+        }
+        self.size = 0;
+    }
+}
+
+impl<K, V> Drop for Level<K, V> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+// Nodes unlinked from the list that are kept to be reused by later inserts,
+// so that a list whose size stays steady stops allocating.
+// The key and value of a node are dropped when it enters the pool, which only
+// keeps the allocation of the `Rc` around.
+struct NodePool<K, V> {
+    free: Vec<Slot<K, V>>,
+    limit: usize,
+}
+
+impl<K, V> NodePool<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    fn new(limit: usize) -> NodePool<K, V> {
+        NodePool {
+            free: Vec::with_capacity(limit),
+            limit,
+        }
+    }
+
+    // Allocate empty slots until the pool holds `nodes` of them or is full.
+    fn fill(&mut self, nodes: usize) {
+        while self.free.len() < nodes.min(self.limit) {
+            self.free.push(Rc::new_uninit());
+        }
+    }
+
+    // Reuse a pooled node for the entry or allocate a new one.
+    fn take(&mut self, key: K, value: V) -> Rc<RefCell<Node<K, V>>> {
+        let node = RefCell::new(Node::new(key, value));
+        match self.free.pop() {
+            Some(mut slot) => {
+                Rc::get_mut(&mut slot)
+                    .expect("pooled slots are not shared")
+                    .write(node);
+                // SAFETY: the slot was initialized just above.
+                unsafe { slot.assume_init() }
             }
-            self.size -= 1;
+            None => Rc::new(node),
+        }
+    }
+
+    // Keep a node that has been unlinked from every level unless the pool is full.
+    // Nodes still referenced from elsewhere are left to be dropped.
+    fn give(&mut self, node: Rc<RefCell<Node<K, V>>>) {
+        if self.free.len() < self.limit
+            && Rc::strong_count(&node) == 1
+            && Rc::weak_count(&node) == 0
+        {
+            let raw = Rc::into_raw(node) as *mut RefCell<Node<K, V>>;
+            // SAFETY: the `Rc` was the only handle to the node, so nothing reads
+            // the node once it is dropped in place. `MaybeUninit<T>` has the size
+            // and alignment of `T`, so the allocation can be taken back as a slot.
+            // Should dropping the entry panic, the allocation is leaked.
+            let slot = unsafe {
+                ptr::drop_in_place(raw);
+                Rc::from_raw(raw as *const MaybeUninit<RefCell<Node<K, V>>>)
+            };
+            self.free.push(slot);
         }
     }
 }
 
-struct Iter<K, V> {
+struct Nodes<K, V> {
     next: Link<K, V>,
 }
 
-impl<K, V> Iterator for Iter<K, V> {
+impl<K, V> Iterator for Nodes<K, V> {
     type Item = Rc<RefCell<Node<K, V>>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let maybe_current: Option<Rc<RefCell<Node<K, V>>>> = self.next.as_ref().map(Rc::clone);
-        if maybe_current.is_some() {
-            let current = maybe_current.unwrap();
+        if let Some(current) = maybe_current {
             self.next = current.borrow_mut().right.as_ref().map(Rc::clone);
             return Some(current);
         }
@@ -243,6 +339,403 @@ impl<K, V> Iterator for Iter<K, V> {
     }
 }
 
+/// Iterator over the entries of a [`SkipList`] in key order, created by [`SkipList::iter`].
+pub struct Iter<'a, K, V> {
+    nodes: Nodes<K, V>,
+    marker: PhantomData<&'a SkipList<K, V>>,
+}
+
+impl<K, V> Iterator for Iter<'_, K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let node = self.nodes.next()?;
+        let node_ref = node.borrow();
+        Some((node_ref.key.clone(), node_ref.value.clone()))
+    }
+}
+
+/// Iterator over the entries of a [`SkipList`] within a range of keys,
+/// created by [`SkipList::range`].
+pub struct Range<'a, K, V> {
+    nodes: Nodes<K, V>,
+    end: Bound<K>,
+    marker: PhantomData<&'a SkipList<K, V>>,
+}
+
+impl<K, V> Iterator for Range<'_, K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let node = self.nodes.next()?;
+        let node_ref = node.borrow();
+        let within = match &self.end {
+            Bound::Included(end) => node_ref.key <= *end,
+            Bound::Excluded(end) => node_ref.key < *end,
+            Bound::Unbounded => true,
+        };
+        if !within {
+            self.nodes.next = None;
+            return None;
+        }
+        Some((node_ref.key.clone(), node_ref.value.clone()))
+    }
+}
+
+/// Builder used to configure how a [`SkipList`] distributes its nodes across levels.
+///
+/// A lower promotion probability creates fewer levels and uses less memory
+/// at the cost of slightly longer searches.
+///
+/// # Example
+/// ```rust
+/// use subway::skiplist::{SkipList, SkipListBuilder};
+///
+/// let list: SkipList<i32, i32> = SkipListBuilder::new()
+///     .probability(0.25)
+///     .max_level(16)
+///     .build();
+/// assert_eq!(list.probability(), 0.25);
+/// assert_eq!(list.max_level(), 16);
+/// ```
+pub struct SkipListBuilder {
+    probability: f64,
+    max_level: Option<usize>,
+    expected_capacity: Option<usize>,
+    rng: Option<Box<dyn RngCore + Send>>,
+    finger: bool,
+    node_pool: usize,
+    capacity_limit: Option<usize>,
+    unique: bool,
+    memory_limit: Option<usize>,
+}
+
+impl SkipListBuilder {
+    /// Create a builder with the default promotion probability of `0.5`
+    /// and at most `32` levels.
+    pub fn new() -> SkipListBuilder {
+        SkipListBuilder {
+            probability: DEFAULT_PROBABILITY,
+            max_level: None,
+            expected_capacity: None,
+            rng: None,
+            finger: false,
+            node_pool: 0,
+            capacity_limit: None,
+            unique: false,
+            memory_limit: None,
+        }
+    }
+
+    /// Set the probability with which a node is promoted to the level above.
+    ///
+    /// # Panics
+    /// If the probability does not lie strictly between `0` and `1`.
+    pub fn probability(mut self, probability: f64) -> SkipListBuilder {
+        assert!(
+            probability > 0.0 && probability < 1.0,
+            "probability must lie between 0 and 1, got {}",
+            probability
+        );
+        self.probability = probability;
+        self
+    }
+
+    /// Set the maximum number of levels the list may grow to.
+    /// Takes precedence over a level derived from [`expected_capacity`](#method.expected_capacity).
+    ///
+    /// # Panics
+    /// If `max_level` is zero.
+    pub fn max_level(mut self, max_level: usize) -> SkipListBuilder {
+        assert!(max_level > 0, "max_level must be at least 1");
+        self.max_level = Some(max_level);
+        self
+    }
+
+    /// Hint the number of entries the list is expected to hold.
+    /// The maximum level is derived as `log(1/p)` of the capacity
+    /// unless set explicitly with [`max_level`](#method.max_level).
+    pub fn expected_capacity(mut self, capacity: usize) -> SkipListBuilder {
+        self.expected_capacity = Some(capacity);
+        self
+    }
+
+    /// Seed the random source used to pick tower heights.
+    /// Lists built with the same seed and fed the same operations
+    /// end up with an identical structure.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::{SkipList, SkipListBuilder};
+    ///
+    /// let mut list: SkipList<i32, i32> = SkipListBuilder::new().seed(42).build();
+    /// list.insert(1, 1);
+    /// ```
+    pub fn seed(mut self, seed: u64) -> SkipListBuilder {
+        self.rng = Some(Box::new(SplitMix64::new(seed)));
+        self
+    }
+
+    /// Use the supplied random number generator to pick tower heights.
+    /// A single random word is drawn per inserted node.
+    /// The generator must be `Send` so that the list can be moved between threads.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rand::prng::XorShiftRng;
+    /// use rand::SeedableRng;
+    /// use subway::skiplist::{SkipList, SkipListBuilder};
+    ///
+    /// let rng = XorShiftRng::from_seed([7; 16]);
+    /// let list: SkipList<i32, i32> = SkipListBuilder::new().rng(rng).build();
+    /// ```
+    pub fn rng<R: RngCore + Send + 'static>(mut self, rng: R) -> SkipListBuilder {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    /// Remember the search path of the last operation so that operations
+    /// on nearby keys do not have to start from the top level.
+    /// See [`SkipList::with_finger`].
+    pub fn finger(mut self, enabled: bool) -> SkipListBuilder {
+        self.finger = enabled;
+        self
+    }
+
+    /// Keep up to `limit` nodes of deleted entries for reuse by later inserts
+    /// instead of returning them to the allocator.
+    /// The key and value of a node are dropped when it enters the pool.
+    /// See [`SkipList::with_capacity`].
+    pub fn node_pool(mut self, limit: usize) -> SkipListBuilder {
+        self.node_pool = limit;
+        self
+    }
+
+    /// Limit the number of entries the list may hold.
+    /// Once the limit is reached [`SkipList::try_insert`] returns
+    /// [`SkipListError::CapacityExceeded`] until entries are deleted.
+    pub fn capacity_limit(mut self, capacity: usize) -> SkipListBuilder {
+        self.capacity_limit = Some(capacity);
+        self
+    }
+
+    /// Reject keys that are already present in the list.
+    /// [`SkipList::try_insert`] returns [`SkipListError::DuplicateKey`]
+    /// for such keys instead of adding another entry.
+    pub fn unique(mut self, enabled: bool) -> SkipListBuilder {
+        self.unique = enabled;
+        self
+    }
+
+    /// Limit the approximate number of bytes the list may use.
+    /// Once an entry would take the [`memory_usage`](struct.SkipList.html#method.memory_usage)
+    /// of the list past the limit, [`SkipList::try_insert`] returns
+    /// [`SkipListError::MemoryLimitExceeded`] until entries are deleted.
+    /// Heap memory owned by keys and values only counts towards the limit
+    /// for lists created through [`build_measured`](#method.build_measured).
+    pub fn memory_limit(mut self, bytes: usize) -> SkipListBuilder {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Create an empty skip list with the configured parameters.
+    pub fn build<K, V>(self) -> SkipList<K, V>
+    where
+        K: Ord + Clone,
+        V: Clone,
+    {
+        self.build_with(no_heap_size)
+    }
+
+    /// Create an empty skip list with the configured parameters that also
+    /// accounts for the heap memory owned by its keys and values in
+    /// [`SkipList::memory_usage`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::{SkipList, SkipListBuilder};
+    ///
+    /// let mut memtable: SkipList<String, Vec<u8>> = SkipListBuilder::new()
+    ///     .memory_limit(64 * 1024 * 1024)
+    ///     .build_measured();
+    /// memtable.insert("key".to_owned(), vec![0; 4096]);
+    /// assert!(memtable.memory_usage() > 4096);
+    /// ```
+    pub fn build_measured<K, V>(self) -> SkipList<K, V>
+    where
+        K: Ord + Clone + HeapSize,
+        V: Clone + HeapSize,
+    {
+        self.build_with(|key, value| key.heap_size() + value.heap_size())
+    }
+
+    fn build_with<K, V>(self, heap_size: fn(&K, &V) -> usize) -> SkipList<K, V>
+    where
+        K: Ord + Clone,
+        V: Clone,
+    {
+        let max_level = match (self.max_level, self.expected_capacity) {
+            (Some(max_level), _) => max_level,
+            (None, Some(capacity)) => level_for_capacity(capacity, self.probability),
+            (None, None) => DEFAULT_MAX_LEVEL,
+        };
+        // seed from the thread local generator once instead of on every insert
+        let rng = self
+            .rng
+            .unwrap_or_else(|| Box::new(SplitMix64::new(random_seed())));
+        let mut levels = Vec::with_capacity(max_level);
+        levels.push(Level::new());
+        SkipList {
+            size: 0,
+            levels,
+            probability: self.probability,
+            max_level,
+            rng,
+            finger: if self.finger {
+                Some(Vec::with_capacity(max_level))
+            } else {
+                None
+            },
+            pool: NodePool::new(self.node_pool),
+            path: Vec::with_capacity(max_level),
+            capacity_limit: self.capacity_limit,
+            unique: self.unique,
+            memory_limit: self.memory_limit,
+            heap_size,
+            heap_bytes: 0,
+        }
+    }
+}
+
+impl Default for SkipListBuilder {
+    fn default() -> SkipListBuilder {
+        SkipListBuilder::new()
+    }
+}
+
+/// Distribution of tower heights produced by [`SkipList::rebalance`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebalanceStrategy {
+    /// Promote every `1/p`-th node to the second level, every `(1/p)^2`-th
+    /// node to the third level and so on. With the default probability
+    /// every `2^k`-th node reaches level `k`.
+    Ideal,
+    /// Draw fresh random tower heights for every node.
+    Randomized,
+}
+
+/// Error returned by [`SkipList::try_insert`] when an entry cannot be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipListError {
+    /// The list already holds as many entries as its
+    /// [`capacity_limit`](struct.SkipListBuilder.html#method.capacity_limit) allows.
+    CapacityExceeded {
+        /// Maximum number of entries the list may hold.
+        capacity: usize,
+    },
+    /// Memory for the bookkeeping of the list could not be allocated.
+    AllocationFailed,
+    /// The key is already present in a list built with
+    /// [`unique`](struct.SkipListBuilder.html#method.unique) enabled.
+    DuplicateKey,
+    /// The entry would take the list past its
+    /// [`memory_limit`](struct.SkipListBuilder.html#method.memory_limit).
+    MemoryLimitExceeded {
+        /// Maximum number of bytes the list may use.
+        limit: usize,
+    },
+}
+
+impl fmt::Display for SkipListError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipListError::CapacityExceeded { capacity } => {
+                write!(
+                    f,
+                    "skip list is full, capacity limit is {} entries",
+                    capacity
+                )
+            }
+            SkipListError::AllocationFailed => write!(f, "skip list failed to allocate memory"),
+            SkipListError::DuplicateKey => write!(f, "key is already present in the skip list"),
+            SkipListError::MemoryLimitExceeded { limit } => {
+                write!(f, "skip list is full, memory limit is {} bytes", limit)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SkipListError {}
+
+/// Statistics describing the shape of a [`SkipList`], returned by [`SkipList::stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkipListStats {
+    /// Number of levels in the list.
+    pub levels: usize,
+    /// Number of nodes in each level, starting from the bottom level.
+    pub nodes_per_level: Vec<usize>,
+    /// Average number of levels spanned by an entry.
+    pub average_height: f64,
+    /// Number of levels spanned by the tallest entry.
+    pub max_height: usize,
+    /// Estimated memory used by the nodes of the list in bytes.
+    /// Heap memory owned by keys and values is not included.
+    pub memory_bytes: usize,
+    /// Average number of nodes visited while searching for a sample of keys.
+    pub average_search_path: f64,
+}
+
+// Bytes allocated for a node, including the reference counts of its `Rc`.
+fn node_size<K, V>() -> usize {
+    mem::size_of::<RefCell<Node<K, V>>>() + 2 * mem::size_of::<usize>()
+}
+
+fn no_heap_size<K, V>(_: &K, _: &V) -> usize {
+    0
+}
+
+fn average(total: usize, count: usize) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    total as f64 / count as f64
+}
+
+// Height of the tower at `index` when every `step`-th node is promoted.
+fn ideal_height(index: usize, step: usize, max_level: usize) -> usize {
+    let mut height = 1;
+    let mut index = index;
+    while index > 0 && index.is_multiple_of(step) && height < max_level {
+        index /= step;
+        height += 1;
+    }
+    height
+}
+
+// Number of levels needed so that the top level of a list holding
+// `capacity` entries is expected to contain a single node.
+fn level_for_capacity(capacity: usize, probability: f64) -> usize {
+    if capacity <= 1 {
+        return 1;
+    }
+    let mut levels = 1;
+    let mut reach = 1.0 / probability;
+    while reach < capacity as f64 && levels < DEFAULT_MAX_LEVEL {
+        reach /= probability;
+        levels += 1;
+    }
+    levels
+}
+
 /// Skip List is an alternative to self balancing sorted data structures like AVL Trees and
 /// Red Black Trees.
 ///
@@ -252,9 +745,49 @@ impl<K, V> Iterator for Iter<K, V> {
 /// to achieve fast read and writes.
 /// For more information about how skip lists work
 /// refer [here](https://en.wikipedia.org/wiki/Skip_list).
+///
+/// A list can be built on one thread and moved to another when its keys and
+/// values are `Send`, but it cannot be shared between threads.
+/// Use [`ConcurrentSkipList`](../concurrent/struct.ConcurrentSkipList.html) for shared access.
+///
+/// ```rust
+/// use std::thread;
+/// use subway::skiplist::SkipList;
+///
+/// let loader = thread::spawn(|| {
+///     let mut list = SkipList::new();
+///     list.insert(1, "one");
+///     list
+/// });
+/// let mut list = loader.join().unwrap();
+/// assert_eq!(list.get(&1), Some("one"));
+/// ```
+///
+/// ```compile_fail
+/// use subway::skiplist::SkipList;
+///
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<SkipList<i32, i32>>();
+/// ```
 pub struct SkipList<K, V> {
     size: usize,
     levels: Vec<Level<K, V>>,
+    probability: f64,
+    max_level: usize,
+    rng: Box<dyn RngCore + Send>,
+    // Predecessor of the last accessed key at every level.
+    // `None` when finger search is disabled.
+    finger: Option<Vec<Link<K, V>>>,
+    pool: NodePool<K, V>,
+    // buffer for search paths reused across operations
+    path: Vec<Insertion<K, V>>,
+    capacity_limit: Option<usize>,
+    unique: bool,
+    memory_limit: Option<usize>,
+    // heap bytes owned by the key and value of an entry
+    heap_size: fn(&K, &V) -> usize,
+    // sum of `heap_size` over all entries, counting every entry once
+    heap_bytes: usize,
 }
 
 enum Insertion<K, V> {
@@ -265,10 +798,11 @@ enum Insertion<K, V> {
 
 impl<K, V> SkipList<K, V>
 where
-    K: Ord + Clone + Display,
+    K: Ord + Clone,
     V: Clone,
 {
     /// Create a empty skip list. This is the recommended way of creating a skip list.
+    /// Use [`SkipListBuilder`] to tune the promotion probability or the number of levels.
     ///
     /// # Example
     /// ```rust
@@ -277,15 +811,67 @@ where
     /// let list: SkipList<i32, i32> = SkipList::new();
     /// ```
     pub fn new() -> SkipList<K, V> {
-        let levels = vec![Level::new()];
-        SkipList { size: 0, levels }
+        SkipListBuilder::new().build()
+    }
+
+    /// Create an empty skip list that remembers the search path of the last
+    /// `insert` or `get`. Operations on a key close to the previous one then
+    /// run in `O(log d)` where `d` is the distance between the two keys,
+    /// which makes appending increasing keys nearly constant time.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::SkipList;
+    ///
+    /// let mut events: SkipList<u64, &str> = SkipList::with_finger();
+    /// for timestamp in 0..1000 {
+    ///     events.insert(timestamp, "tick");
+    /// }
+    /// assert_eq!(events.get(&999), Some("tick"));
+    /// ```
+    pub fn with_finger() -> SkipList<K, V> {
+        SkipListBuilder::new().finger(true).build()
+    }
+
+    /// Create an empty skip list with nodes preallocated for `capacity` entries.
+    /// Nodes of deleted entries are kept for reuse, so inserts do not allocate
+    /// as long as the list holds no more than `capacity` entries.
+    /// Key and value types whose `clone` allocates, like `String`, still do so
+    /// on insert since every level holds its own copy of the entry.
+    ///
+    /// Nodes are reference counted and stable Rust cannot place an `Rc` in a
+    /// custom allocator, so the pool is the only allocation strategy the list
+    /// offers. Allocations outside the pool go through the global allocator,
+    /// which can be replaced with `#[global_allocator]`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::SkipList;
+    ///
+    /// let mut orders: SkipList<u64, u64> = SkipList::with_capacity(1000);
+    /// for id in 0..1000 {
+    ///     orders.insert(id, id * 10);
+    /// }
+    /// orders.delete(&0);
+    /// orders.insert(1000, 10000);
+    /// assert_eq!(orders.len(), 1000);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> SkipList<K, V> {
+        // a list of `n` entries holds `n / (1 - p)` nodes on average
+        let nodes = (capacity as f64 / (1.0 - DEFAULT_PROBABILITY)) as usize + DEFAULT_MAX_LEVEL;
+        let mut list = SkipListBuilder::new()
+            .expected_capacity(capacity)
+            .node_pool(nodes)
+            .build();
+        list.pool.fill(nodes);
+        list
     }
 
     /// Insert the given key and value into the list.
     ///
     /// # Arguments
     /// * _key_ - The key by which the value is to be accessed.
-    ///             This is also used as the sort key.
+    ///   This is also used as the sort key.
     /// * _value_ - The value to store associated with the key.
     ///
     /// # Example
@@ -299,43 +885,212 @@ where
     /// assert_eq!(cakes.len(), 2);
     /// ```
     ///
+    /// # Panics
+    /// If the entry is rejected by a list configured with
+    /// [`capacity_limit`](struct.SkipListBuilder.html#method.capacity_limit),
+    /// [`memory_limit`](struct.SkipListBuilder.html#method.memory_limit) or
+    /// [`unique`](struct.SkipListBuilder.html#method.unique).
+    /// Use [`try_insert`](#method.try_insert) to handle these cases.
     pub fn insert(&mut self, key: K, value: V) {
-        if self.levels.len() > 0 {
-            let mut insertion_path = Vec::new();
+        if let Err(error) = self.try_insert(key, value) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Insert the given key and value into the list, reporting instead of
+    /// panicking when the entry cannot be added.
+    ///
+    /// Allocation failures are reported for the buffers the list grows while
+    /// inserting. The node allocations themselves still abort on out of memory,
+    /// since stable Rust cannot allocate an `Rc` fallibly. Nodes taken from the
+    /// pool of a list created through [`with_capacity`](#method.with_capacity)
+    /// do not allocate.
+    ///
+    /// # Arguments
+    /// * _key_ - The key by which the value is to be accessed.
+    /// * _value_ - The value to store associated with the key.
+    ///
+    /// # Returns
+    /// * [`SkipListError::CapacityExceeded`] if the list holds as many entries
+    ///   as its capacity limit allows.
+    /// * [`SkipListError::DuplicateKey`] if the list only accepts unique keys
+    ///   and the key is present.
+    /// * [`SkipListError::MemoryLimitExceeded`] if the entry would take the
+    ///   list past its memory limit.
+    /// * [`SkipListError::AllocationFailed`] if memory could not be reserved.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::{SkipList, SkipListBuilder, SkipListError};
+    ///
+    /// let mut index: SkipList<u64, &str> = SkipListBuilder::new()
+    ///     .capacity_limit(2)
+    ///     .unique(true)
+    ///     .build();
+    /// assert_eq!(index.try_insert(1, "one"), Ok(()));
+    /// assert_eq!(index.try_insert(1, "uno"), Err(SkipListError::DuplicateKey));
+    /// assert_eq!(index.try_insert(2, "two"), Ok(()));
+    /// assert_eq!(
+    ///     index.try_insert(3, "three"),
+    ///     Err(SkipListError::CapacityExceeded { capacity: 2 })
+    /// );
+    /// ```
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), SkipListError> {
+        if let Some(capacity) = self.capacity_limit {
+            if self.size >= capacity {
+                return Err(SkipListError::CapacityExceeded { capacity });
+            }
+        }
+        let entry_heap = (self.heap_size)(&key, &value);
+        if let Some(limit) = self.memory_limit {
+            // an entry takes at least one node
+            if self.memory_usage() + node_size::<K, V>() + entry_heap > limit {
+                return Err(SkipListError::MemoryLimitExceeded { limit });
+            }
+        }
+        if !self.levels.is_empty() {
+            self.reserve_for_insert()?;
+            let mut insertion_path = mem::take(&mut self.path);
             self.bisect_levels(&key, &mut insertion_path);
-            let mut is_head = false;
-            match insertion_path.get(0).unwrap() {
-                Insertion::Before => {
-                    is_head = true;
+            if self.unique {
+                let duplicate = match insertion_path.first() {
+                    Some(Insertion::After(node)) => node.borrow().cmp(&key) == Ordering::Equal,
+                    _ => false,
+                };
+                if duplicate {
+                    insertion_path.clear();
+                    self.path = insertion_path;
+                    return Err(SkipListError::DuplicateKey);
                 }
-                _ => (),
             }
-            let mut prev_level_node = self.insert_at_position(0, &key, &value, &insertion_path[0]);
-            let mut i = 1;
-            while i < self.levels.len() {
-                let current_level_node =
-                    self.insert_at_position(i, &key, &value, &insertion_path[i]);
-                prev_level_node.borrow_mut().up = Some(Rc::downgrade(&current_level_node));
-                current_level_node.borrow_mut().down = Some(Rc::clone(&prev_level_node));
-                prev_level_node = Rc::clone(&current_level_node);
-                i += 1;
-            }
-            // create more levels probabilistically if more than one node present
-            // and newly added node is not head
-            // we expect more than one node to be present to avoid
-            // let head_ref = self.levels[0].head.as_ref().unwrap();
-            if self.levels[0].size > 1 {
-                while self.flip_coin() && !is_head {
-                    self.add_level();
-                    let curr_size = self.levels.len();
-                    let new_node = self.levels[curr_size - 1].insert(key.clone(), value.clone());
+            self.insert_along(key, value, entry_heap, insertion_path);
+        }
+        Ok(())
+    }
+
+    // Insert an entry at the position found by `bisect_levels`
+    // and hand the path buffer back to the list.
+    fn insert_along(
+        &mut self,
+        key: K,
+        value: V,
+        entry_heap: usize,
+        mut insertion_path: Vec<Insertion<K, V>>,
+    ) {
+        let is_head = matches!(insertion_path[0], Insertion::Before);
+        let bottom = self.insert_at_position(0, &key, &value, &insertion_path[0]);
+        let mut prev_level_node = Rc::clone(&bottom);
+        let height = self.random_height();
+        let mut i = 1;
+        while i < height && i < self.levels.len() {
+            let current_level_node = self.insert_at_position(i, &key, &value, &insertion_path[i]);
+            prev_level_node.borrow_mut().up = Some(Rc::downgrade(&current_level_node));
+            current_level_node.borrow_mut().down = Some(Rc::clone(&prev_level_node));
+            prev_level_node = current_level_node;
+            i += 1;
+        }
+        if is_head && i < self.levels.len() {
+            self.take_over_head_tower(&prev_level_node, &key, &value);
+        }
+        // create more levels if the tower of the new node is
+        // taller than the list and more than one node is present
+        if self.levels[0].size > 1 {
+            while i < height {
+                self.add_level();
+                let curr_size = self.levels.len();
+                if is_head {
+                    // the new level starts with the new head, extend the
+                    // tower of the previous head next to it
+                    let below = prev_level_node
+                        .borrow()
+                        .right
+                        .as_ref()
+                        .map(Rc::clone)
+                        .unwrap();
+                    let new_head = self.levels[curr_size - 1]
+                        .head
+                        .as_ref()
+                        .map(Rc::clone)
+                        .unwrap();
+                    let (previous_key, previous_value) = {
+                        let below_ref = below.borrow();
+                        (below_ref.key.clone(), below_ref.value.clone())
+                    };
+                    let node = self.pool.take(previous_key, previous_value);
+                    let copy = self.levels[curr_size - 1].insert_after(node, Rc::clone(&new_head));
+                    below.borrow_mut().up = Some(Rc::downgrade(&copy));
+                    copy.borrow_mut().down = Some(below);
+                    prev_level_node = new_head;
+                } else {
+                    let node = self.pool.take(key.clone(), value.clone());
+                    let new_node = self.levels[curr_size - 1].insert(node);
                     prev_level_node.borrow_mut().up = Some(Rc::downgrade(&new_node));
                     new_node.borrow_mut().down = Some(Rc::clone(&prev_level_node));
-                    prev_level_node = Rc::clone(&new_node);
+                    prev_level_node = new_node;
                 }
+                i += 1;
+            }
+        }
+        self.size += 1;
+        self.heap_bytes += entry_heap;
+        // the new node is the predecessor of its key in every level it spans
+        if let Some(finger) = self.finger.as_mut() {
+            finger.clear();
+            let mut maybe_node = Some(bottom);
+            for level in 0..self.levels.len() {
+                finger.push(match (maybe_node.take(), insertion_path.get(level)) {
+                    (Some(node), _) => {
+                        maybe_node = node.borrow().up.as_ref().and_then(Weak::upgrade);
+                        Some(node)
+                    }
+                    (None, Some(Insertion::After(node))) => Some(Rc::clone(node)),
+                    (None, _) => None,
+                });
+            }
+        }
+        insertion_path.clear();
+        self.path = insertion_path;
+    }
+
+    // Replace the value of the newest entry with the key, or insert the entry
+    // if the key is absent, searching the list once. The capacity and memory
+    // limits of the list are not checked.
+    pub(crate) fn upsert(&mut self, key: K, value: V) -> Option<V> {
+        let mut search_path = mem::take(&mut self.path);
+        self.bisect_levels(&key, &mut search_path);
+        let existing = match search_path.first() {
+            Some(Insertion::After(node)) if node.borrow().cmp(&key) == Ordering::Equal => {
+                Some(Rc::clone(node))
+            }
+            _ => None,
+        };
+        let bottom = match existing {
+            Some(bottom) => bottom,
+            None => {
+                let entry_heap = (self.heap_size)(&key, &value);
+                self.insert_along(key, value, entry_heap, search_path);
+                return None;
             }
-            self.size += 1;
+        };
+        self.heap_bytes += (self.heap_size)(&key, &value);
+        // every level the entry spans holds its own copy of the value
+        let mut maybe_up = bottom.borrow().up.as_ref().and_then(Weak::upgrade);
+        while let Some(node) = maybe_up {
+            node.borrow_mut().value = value.clone();
+            maybe_up = node.borrow().up.as_ref().and_then(Weak::upgrade);
         }
+        let previous = mem::replace(&mut bottom.borrow_mut().value, value);
+        self.heap_bytes -= (self.heap_size)(&key, &previous);
+        if let Some(finger) = self.finger.as_mut() {
+            finger.clear();
+            finger.extend(search_path.drain(..).map(|insertion| match insertion {
+                Insertion::After(node) => Some(node),
+                Insertion::Before => None,
+            }));
+        }
+        search_path.clear();
+        self.path = search_path;
+        Some(previous)
     }
 
     /// Get the value associated with a key if it exists.
@@ -355,24 +1110,25 @@ where
     /// assert_eq!(maybe_chocolate.unwrap(), "Strawberry Topping");
     /// ```
     pub fn get(&mut self, key: &K) -> Option<V> {
-        let size = self.levels.len();
-        let mut i = 0;
-        let mut maybe_prev = self.levels[size - i - 1].bisect(key);
-        i += 1;
-        while i < size && maybe_prev.is_some() {
-            let prev = maybe_prev.take().unwrap();
-            let after = prev.borrow().down.as_ref().map(Rc::clone).unwrap();
-            maybe_prev = self.levels[size - i - 1].bisect_after(&after, key);
-            i += 1;
-        }
-        if maybe_prev.is_some() {
-            let found = maybe_prev.take().unwrap();
-            return match found.borrow().cmp(key) {
-                Ordering::Equal => Some(found.borrow().value.clone()),
+        let mut search_path = mem::take(&mut self.path);
+        self.bisect_levels(key, &mut search_path);
+        let found = match search_path.first() {
+            Some(Insertion::After(node)) => match node.borrow().cmp(key) {
+                Ordering::Equal => Some(node.borrow().value.clone()),
                 _ => None,
-            };
+            },
+            _ => None,
+        };
+        if let Some(finger) = self.finger.as_mut() {
+            finger.clear();
+            finger.extend(search_path.drain(..).map(|insertion| match insertion {
+                Insertion::After(node) => Some(node),
+                Insertion::Before => None,
+            }));
         }
-        None
+        search_path.clear();
+        self.path = search_path;
+        found
     }
 
     /// Delete the value associated with the key.
@@ -391,11 +1147,89 @@ where
     /// cakes.delete(&100);
     /// ```
     pub fn delete(&mut self, key: &K) {
-        let size = self.levels.len();
-        for i in 0..size {
-            self.levels[i].delete(key);
+        let mut search_path = mem::take(&mut self.path);
+        self.bisect_levels(key, &mut search_path);
+        let to_delete = match search_path.first() {
+            Some(Insertion::After(node)) if node.borrow().cmp(key) == Ordering::Equal => {
+                Some(Rc::clone(node))
+            }
+            _ => None,
+        };
+        search_path.clear();
+        self.path = search_path;
+        let to_delete = match to_delete {
+            Some(node) => node,
+            None => return,
+        };
+        // the finger may point at a deleted node
+        if let Some(finger) = self.finger.as_mut() {
+            finger.clear();
+        }
+        let freed = {
+            let node = to_delete.borrow();
+            (self.heap_size)(&node.key, &node.value)
+        };
+        self.heap_bytes = self.heap_bytes.saturating_sub(freed);
+        let is_head = to_delete.borrow().left.is_none();
+        // remove the whole tower of the node top down
+        // so that every node is released by the one above it first
+        let mut level = 0;
+        let mut top = to_delete;
+        loop {
+            let maybe_up = top.borrow().up.as_ref().and_then(Weak::upgrade);
+            match maybe_up {
+                Some(up) => top = up,
+                None => break,
+            }
+            level += 1;
+        }
+        let mut maybe_node = Some(top);
+        while let Some(node) = maybe_node {
+            maybe_node = node.borrow().down.as_ref().map(Rc::clone);
+            self.levels[level].unlink(&node);
+            if let Some(below) = maybe_node.as_ref() {
+                below.borrow_mut().up = None;
+            }
+            self.pool.give(node);
+            level = level.saturating_sub(1);
         }
         self.size = self.levels[0].size;
+        if is_head {
+            self.elect_head();
+        }
+        self.remove_empty_levels();
+    }
+
+    /// Remove levels that are taller than needed for the current number of entries
+    /// and release unused memory held by the list.
+    /// Lists that grew large and were then drained keep the levels they needed
+    /// when they were large until this is called.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::SkipList;
+    ///
+    /// let mut list: SkipList<i32, i32> = SkipList::new();
+    /// for i in 0..1000 {
+    ///     list.insert(i, i);
+    /// }
+    /// for i in 10..1000 {
+    ///     list.delete(&i);
+    /// }
+    /// list.shrink_to_fit();
+    /// assert!(list.stats().levels <= 4);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        let needed = level_for_capacity(self.size, self.probability);
+        while self.levels.len() > needed {
+            self.pop_level();
+        }
+        self.remove_empty_levels();
+        self.levels.shrink_to_fit();
+        if let Some(finger) = self.finger.as_mut() {
+            finger.clear();
+            finger.shrink_to_fit();
+        }
     }
 
     /// Find the largest key after which the supplied key can be inserted.
@@ -432,10 +1266,10 @@ where
                 prev_node = maybe_prev.as_ref().map(Rc::clone);
                 let prev = maybe_prev.take().unwrap();
                 let maybe_after = prev.borrow().down.as_ref().map(Rc::clone);
-                if maybe_after.is_none() {
-                    return Some(prev.borrow().key.clone());
-                }
-                let after = maybe_after.unwrap();
+                let after = match maybe_after {
+                    Some(after) => after,
+                    None => return Some(prev.borrow().key.clone()),
+                };
                 i += 1;
                 maybe_prev = self.levels[size - i - 1].bisect_after(&after, key);
             }
@@ -462,7 +1296,7 @@ where
     /// ```
     pub fn collect(&self) -> Vec<(K, V)> {
         let mut values = Vec::new();
-        self.iter().for_each(|node_ref| {
+        self.nodes().for_each(|node_ref| {
             let key = node_ref.borrow().key.clone();
             let value = node_ref.borrow().value.clone();
             values.push((key, value));
@@ -470,15 +1304,59 @@ where
         values
     }
 
-    /// Returns the size of the list.
+    /// Iterate over the entries of the list in key order.
+    /// Every level holds its own copy of an entry, so entries are yielded as clones.
     ///
     /// # Example
     /// ```rust
     /// use subway::skiplist::SkipList;
     ///
-    /// let mut cakes: SkipList<i32, String> = SkipList::new();
-    /// cakes.insert(20, "Strawberry Topping".to_owned());
-    /// cakes.insert(40, "Chocolate Glaze".to_owned());
+    /// let mut list = SkipList::new();
+    /// list.insert(2, "two");
+    /// list.insert(1, "one");
+    /// let keys: Vec<i32> = list.iter().map(|(key, _)| key).collect();
+    /// assert_eq!(keys, vec![1, 2]);
+    /// ```
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            nodes: self.nodes(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterate over the entries whose keys fall in the given range, in key order.
+    /// The first entry is found by a search in `O(log n)` rather than a scan.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::SkipList;
+    ///
+    /// let mut list = SkipList::new();
+    /// for i in 0..10 {
+    ///     list.insert(i, i * 10);
+    /// }
+    /// let entries: Vec<(i32, i32)> = list.range(3..6).collect();
+    /// assert_eq!(entries, vec![(3, 30), (4, 40), (5, 50)]);
+    /// ```
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        Range {
+            nodes: Nodes {
+                next: self.seek(range.start_bound()),
+            },
+            end: range.end_bound().cloned(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the size of the list.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::SkipList;
+    ///
+    /// let mut cakes: SkipList<i32, String> = SkipList::new();
+    /// cakes.insert(20, "Strawberry Topping".to_owned());
+    /// cakes.insert(40, "Chocolate Glaze".to_owned());
     /// cakes.insert(100, "Lemon Cream".to_owned());
     /// assert_eq!(cakes.len(), 3);
     /// ```
@@ -499,6 +1377,198 @@ where
         self.size == 0
     }
 
+    /// Collect statistics describing the shape of the list.
+    /// Useful to notice when a list has degenerated, for example when bulk
+    /// deletes leave tall towers behind.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::SkipList;
+    ///
+    /// let mut list: SkipList<i32, i32> = SkipList::new();
+    /// for i in 0..100 {
+    ///     list.insert(i, i);
+    /// }
+    /// let stats = list.stats();
+    /// assert_eq!(stats.nodes_per_level[0], 100);
+    /// assert!(stats.max_height <= stats.levels);
+    /// ```
+    pub fn stats(&self) -> SkipListStats {
+        let nodes_per_level: Vec<usize> = self.levels.iter().map(|level| level.size).collect();
+        let total_nodes: usize = nodes_per_level.iter().sum();
+        let max_height = self
+            .nodes()
+            .map(|node| {
+                let mut height = 1;
+                let mut maybe_up = node.borrow().up.as_ref().and_then(Weak::upgrade);
+                while let Some(up) = maybe_up {
+                    height += 1;
+                    maybe_up = up.borrow().up.as_ref().and_then(Weak::upgrade);
+                }
+                height
+            })
+            .max()
+            .unwrap_or(0);
+        let memory_bytes = self.structure_memory(total_nodes);
+        // search for keys spread evenly across the list
+        let step = (self.size / STATS_SAMPLE_SIZE).max(1);
+        let sample: Vec<usize> = self
+            .nodes()
+            .step_by(step)
+            .map(|node| self.search_path_length(&node.borrow().key))
+            .collect();
+        SkipListStats {
+            levels: self.levels.len(),
+            average_height: average(total_nodes, nodes_per_level[0]),
+            max_height,
+            memory_bytes,
+            average_search_path: average(sample.iter().sum(), sample.len()),
+            nodes_per_level,
+        }
+    }
+
+    /// Rebuild the towers of the list in `O(n)` without changing its contents.
+    /// Long running churn can leave a list with skewed level sizes;
+    /// rebalancing restores the expected `O(log n)` search behaviour.
+    ///
+    /// # Arguments
+    /// * _strategy_ - Whether tower heights are assigned deterministically or drawn at random.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::{RebalanceStrategy, SkipList};
+    ///
+    /// let mut list: SkipList<i32, i32> = SkipList::new();
+    /// for i in 0..8 {
+    ///     list.insert(i, i);
+    /// }
+    /// list.rebalance(RebalanceStrategy::Ideal);
+    /// assert_eq!(list.stats().nodes_per_level, vec![8, 4, 2]);
+    /// ```
+    pub fn rebalance(&mut self, strategy: RebalanceStrategy) {
+        let entries = self.drain();
+        let heights = match strategy {
+            RebalanceStrategy::Ideal => {
+                let step = ((1.0 / self.probability + 0.5) as usize).max(2);
+                (0..entries.len())
+                    .map(|index| ideal_height(index, step, self.max_level))
+                    .collect()
+            }
+            RebalanceStrategy::Randomized => {
+                (0..entries.len()).map(|_| self.random_height()).collect()
+            }
+        };
+        self.build_sorted(entries, heights);
+    }
+
+    /// Remove all entries from the list.
+    /// Nodes are freed iteratively so that clearing very long lists
+    /// does not overflow the stack.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::SkipList;
+    ///
+    /// let mut cakes: SkipList<i32, String> = SkipList::new();
+    /// cakes.insert(20, "Strawberry Topping".to_owned());
+    /// cakes.insert(40, "Chocolate Glaze".to_owned());
+    /// cakes.clear();
+    /// assert_eq!(cakes.is_empty(), true);
+    /// ```
+    pub fn clear(&mut self) {
+        if let Some(finger) = self.finger.as_mut() {
+            finger.clear();
+        }
+        while self.levels.len() > 1 {
+            self.pop_level();
+        }
+        let bottom = mem::replace(&mut self.levels[0], Level::new());
+        self.release_level(bottom);
+        self.size = 0;
+        self.heap_bytes = 0;
+    }
+
+    /// Returns the probability with which nodes are promoted to the next level.
+    pub fn probability(&self) -> f64 {
+        self.probability
+    }
+
+    /// Returns the maximum number of levels the list may grow to.
+    pub fn max_level(&self) -> usize {
+        self.max_level
+    }
+
+    /// Returns the maximum number of entries the list may hold, if limited.
+    pub fn capacity_limit(&self) -> Option<usize> {
+        self.capacity_limit
+    }
+
+    /// Returns whether the list rejects keys that are already present.
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+
+    /// Returns the approximate number of bytes used by the list.
+    ///
+    /// This covers the nodes of every level and, for lists created through
+    /// [`SkipListBuilder::build_measured`], the heap memory owned by keys and
+    /// values. Every level a key spans holds its own copy of the entry, so heap
+    /// memory is scaled by the average tower height. Nodes kept in the pool for
+    /// reuse are not counted. Runs in `O(levels)`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::skiplist::{SkipList, SkipListBuilder};
+    ///
+    /// let mut list: SkipList<u64, String> = SkipListBuilder::new().build_measured();
+    /// let empty = list.memory_usage();
+    /// list.insert(1, "x".repeat(1000));
+    /// assert!(list.memory_usage() >= empty + 1000);
+    /// list.delete(&1);
+    /// assert_eq!(list.memory_usage(), empty);
+    /// ```
+    pub fn memory_usage(&self) -> usize {
+        let total_nodes: usize = self.levels.iter().map(|level| level.size).sum();
+        let heap_bytes = if self.size == 0 {
+            0
+        } else {
+            (self.heap_bytes as u128 * total_nodes as u128 / self.size as u128) as usize
+        };
+        self.structure_memory(total_nodes) + heap_bytes
+    }
+
+    /// Returns the maximum number of bytes the list may use, if limited.
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    // Memory used by the list itself and its nodes, excluding the heap
+    // memory owned by keys and values.
+    fn structure_memory(&self, total_nodes: usize) -> usize {
+        mem::size_of::<Self>()
+            + self.levels.capacity() * mem::size_of::<Level<K, V>>()
+            + total_nodes * node_size::<K, V>()
+    }
+
+    // Grow the buffers an insert pushes to up front, so that running out of
+    // memory is reported instead of aborting halfway through linking a tower.
+    fn reserve_for_insert(&mut self) -> Result<(), SkipListError> {
+        let levels = self.max_level.saturating_sub(self.levels.len());
+        self.levels
+            .try_reserve(levels)
+            .map_err(|_| SkipListError::AllocationFailed)?;
+        self.path
+            .try_reserve(self.max_level)
+            .map_err(|_| SkipListError::AllocationFailed)?;
+        if let Some(finger) = self.finger.as_mut() {
+            // the finger is cleared before it is refilled
+            finger
+                .try_reserve(self.max_level.saturating_sub(finger.len()))
+                .map_err(|_| SkipListError::AllocationFailed)?;
+        }
+        Ok(())
+    }
+
     fn insert_at_position(
         &mut self,
         level: usize,
@@ -506,49 +1576,146 @@ where
         value: &V,
         insertion: &Insertion<K, V>,
     ) -> Rc<RefCell<Node<K, V>>> {
-        return match insertion {
-            Insertion::Before => {
-                let new_head = self.levels[level].insert(key.clone(), value.clone());
-                Rc::clone(&new_head)
-            }
-            Insertion::After(node) => {
-                let new_node =
-                    self.levels[level].insert_after(key.clone(), value.clone(), Rc::clone(node));
-                Rc::clone(&new_node)
-            }
-        };
+        let new_node = self.pool.take(key.clone(), value.clone());
+        match insertion {
+            Insertion::Before => self.levels[level].insert(new_node),
+            Insertion::After(node) => self.levels[level].insert_after(new_node, Rc::clone(node)),
+        }
     }
 
     /// Find the points of insertion in each level to complete an insert to the list.
     fn bisect_levels(&self, key: &K, output: &mut Vec<Insertion<K, V>>) {
         let size = self.levels.len();
-        let mut i = 0;
-        while i < size {
-            let idx = size - i - 1;
-            let head = self.levels[idx].head.as_ref();
-            if head.is_some() {
-                let head_ref = head.unwrap();
-                match head_ref.borrow().cmp(key) {
-                    Ordering::Greater => {
-                        // insert before head
-                        output.push(Insertion::Before);
-                    }
-                    Ordering::Equal | Ordering::Less => {
-                        let insertion_point = self.levels[idx].bisect_after(head_ref, key).unwrap();
-                        output.push(Insertion::After(insertion_point));
-                    }
+        let (start_level, start_node) =
+            match self.finger_start(key).or_else(|| self.head_start(key)) {
+                Some(start) => start,
+                None => {
+                    // key is smaller than the head of every level
+                    output.extend((0..size).map(|_| Insertion::Before));
+                    return;
                 }
-            } else {
-                // insert new head into empty level
-                output.push(Insertion::Before)
+            };
+        // levels above the start either keep the predecessor recorded
+        // by the finger or have a head larger than the key
+        let mut i = size - 1;
+        while i > start_level {
+            let maybe_prev = self.finger.as_ref().and_then(|finger| match finger.get(i) {
+                Some(Some(node)) if node.borrow().cmp(key) != Ordering::Greater => {
+                    Some(Rc::clone(node))
+                }
+                _ => None,
+            });
+            output.push(match maybe_prev {
+                Some(prev) => Insertion::After(prev),
+                None => Insertion::Before,
+            });
+            i -= 1;
+        }
+        // walk right and then down starting from a node that is not larger than the key
+        let mut node = start_node;
+        let mut level = start_level;
+        loop {
+            let insertion_point = self.levels[level].bisect_after(&node, key).unwrap();
+            let maybe_down = insertion_point.borrow().down.as_ref().map(Rc::clone);
+            output.push(Insertion::After(insertion_point));
+            match maybe_down {
+                Some(down) if level > 0 => node = down,
+                _ => break,
             }
-            i += 1;
+            level -= 1;
+        }
+        // levels below a node without a down link are searched from their heads
+        while level > 0 {
+            level -= 1;
+            output.push(match self.levels[level].head.as_ref() {
+                Some(head) if head.borrow().cmp(key) != Ordering::Greater => {
+                    Insertion::After(self.levels[level].bisect_after(head, key).unwrap())
+                }
+                _ => Insertion::Before,
+            });
         }
         output.reverse()
     }
 
-    fn iter(&self) -> Iter<K, V> {
-        Iter {
+    // Start a search from the highest level whose head is not larger than the key.
+    fn head_start(&self, key: &K) -> Option<SearchStart<K, V>> {
+        (0..self.levels.len()).rev().find_map(|level| {
+            self.levels[level]
+                .head
+                .as_ref()
+                .filter(|head| head.borrow().cmp(key) != Ordering::Greater)
+                .map(|head| (level, Rc::clone(head)))
+        })
+    }
+
+    // Start a search from the lowest level at which the finger left by the
+    // previous operation is still the predecessor of the key. Levels above it
+    // are then guaranteed to hold valid predecessors as well.
+    // If no such level exists but the topmost finger precedes the key
+    // the search walks right from the top of the finger.
+    fn finger_start(&self, key: &K) -> Option<SearchStart<K, V>> {
+        let finger = self.finger.as_ref()?;
+        if finger.len() != self.levels.len() {
+            return None;
+        }
+        let precedes = |node: &Rc<RefCell<Node<K, V>>>| node.borrow().cmp(key) != Ordering::Greater;
+        for (level, maybe_node) in finger.iter().enumerate() {
+            if let Some(node) = maybe_node.as_ref().filter(|node| precedes(node)) {
+                let next_is_greater = node
+                    .borrow()
+                    .right
+                    .as_ref()
+                    .is_none_or(|next| next.borrow().cmp(key) == Ordering::Greater);
+                if next_is_greater {
+                    return Some((level, Rc::clone(node)));
+                }
+            }
+        }
+        let top = finger.len() - 1;
+        finger[top]
+            .as_ref()
+            .filter(|node| precedes(node))
+            .map(|node| (top, Rc::clone(node)))
+    }
+
+    // First node of the bottom level whose key is not before the bound.
+    // Walks right and then down without touching the finger.
+    fn seek(&self, bound: Bound<&K>) -> Link<K, V> {
+        let before = |node: &Rc<RefCell<Node<K, V>>>| match bound {
+            Bound::Included(start) => node.borrow().key < *start,
+            Bound::Excluded(start) => node.borrow().key <= *start,
+            Bound::Unbounded => false,
+        };
+        let mut maybe_pred: Link<K, V> = None;
+        for level in (0..self.levels.len()).rev() {
+            let mut pred = match maybe_pred.take() {
+                Some(pred) => pred,
+                None => match self.levels[level].head.as_ref().filter(|head| before(head)) {
+                    Some(head) => Rc::clone(head),
+                    None => continue,
+                },
+            };
+            loop {
+                let maybe_next = pred.borrow().right.as_ref().map(Rc::clone);
+                match maybe_next.filter(|next| before(next)) {
+                    Some(next) => pred = next,
+                    None => break,
+                }
+            }
+            maybe_pred = if level > 0 {
+                pred.borrow().down.as_ref().map(Rc::clone)
+            } else {
+                Some(pred)
+            };
+        }
+        match maybe_pred {
+            Some(pred) => pred.borrow().right.as_ref().map(Rc::clone),
+            None => self.levels[0].head.as_ref().map(Rc::clone),
+        }
+    }
+
+    fn nodes(&self) -> Nodes<K, V> {
+        Nodes {
             next: self.levels[0].head.as_ref().map(Rc::clone),
         }
     }
@@ -560,33 +1727,254 @@ where
         let key: K = prev_head.borrow().key.clone();
         let value: V = prev_head.borrow().value.clone();
         let mut new_level = Level::new();
-        let new_head = new_level.insert(key, value);
+        let new_head = new_level.insert(self.pool.take(key, value));
         prev_head.borrow_mut().up = Some(Rc::downgrade(&new_head));
         new_head.borrow_mut().down = Some(prev_head);
         self.levels.push(new_level);
     }
 
-    fn flip_coin(&self) -> bool {
-        let random = rand::thread_rng().gen_range(0.0, 1.0);
-        return random > 0.50;
+    // Remove all entries moving them out of the list in sorted order.
+    pub(crate) fn drain(&mut self) -> Vec<(K, V)> {
+        if let Some(finger) = self.finger.as_mut() {
+            finger.clear();
+        }
+        while self.levels.len() > 1 {
+            self.pop_level();
+        }
+        let mut entries = Vec::with_capacity(self.size);
+        let mut maybe_node = self.levels[0].head.take();
+        while let Some(node) = maybe_node {
+            maybe_node = node.borrow_mut().right.take();
+            // nothing else refers to the node once the upper levels are gone
+            let entry = match Rc::try_unwrap(node) {
+                Ok(cell) => {
+                    let node = cell.into_inner();
+                    (node.key, node.value)
+                }
+                Err(node) => (node.borrow().key.clone(), node.borrow().value.clone()),
+            };
+            entries.push(entry);
+        }
+        self.levels[0].size = 0;
+        self.size = 0;
+        entries
     }
 
-    fn print(&self) {
-        let size = self.levels.len();
-        println!("number of levels is {0}", self.levels.len());
+    // Build the levels of an empty list from entries sorted by key
+    // giving the i-th entry a tower of `heights[i]` levels.
+    fn build_sorted(&mut self, entries: Vec<(K, V)>, heights: Vec<usize>) {
+        let mut tails: Vec<Rc<RefCell<Node<K, V>>>> = Vec::new();
+        for ((key, value), height) in entries.into_iter().zip(heights) {
+            let mut maybe_below: Link<K, V> = None;
+            for level in 0..height.clamp(1, self.max_level) {
+                if level == self.levels.len() {
+                    self.levels.push(Level::new());
+                }
+                let node = self.pool.take(key.clone(), value.clone());
+                let node = match tails.get(level) {
+                    Some(tail) => self.levels[level].insert_after(node, Rc::clone(tail)),
+                    None => self.levels[level].push_front(node),
+                };
+                if let Some(below) = maybe_below {
+                    below.borrow_mut().up = Some(Rc::downgrade(&node));
+                    node.borrow_mut().down = Some(below);
+                }
+                if level == tails.len() {
+                    tails.push(Rc::clone(&node));
+                } else {
+                    tails[level] = Rc::clone(&node);
+                }
+                maybe_below = Some(node);
+            }
+        }
+        self.size = self.levels[0].size;
+        self.elect_head();
+    }
+
+    // Fill an empty list with entries sorted by key in `O(n)`.
+    // Towers are given the supplied heights or random ones.
+    #[cfg(any(feature = "std", feature = "serde"))]
+    pub(crate) fn load_sorted(&mut self, entries: Vec<(K, V)>, heights: Option<Vec<usize>>) {
+        debug_assert!(self.is_empty());
+        self.heap_bytes += entries
+            .iter()
+            .map(|(key, value)| (self.heap_size)(key, value))
+            .sum::<usize>();
+        let heights = match heights {
+            Some(heights) => heights,
+            None => (0..entries.len()).map(|_| self.random_height()).collect(),
+        };
+        self.build_sorted(entries, heights);
+    }
+
+    // Call `visit` with every entry in key order along with the height of its tower.
+    // Stops at and returns the first error.
+    #[cfg(any(feature = "std", feature = "serde"))]
+    pub(crate) fn visit_entries<E, F>(&self, mut visit: F) -> Result<(), E>
+    where
+        F: FnMut(&K, &V, usize) -> Result<(), E>,
+    {
+        for node in self.nodes() {
+            let mut height = 1;
+            let mut maybe_up = node.borrow().up.as_ref().and_then(Weak::upgrade);
+            while let Some(up) = maybe_up {
+                height += 1;
+                maybe_up = up.borrow().up.as_ref().and_then(Weak::upgrade);
+            }
+            let node_ref = node.borrow();
+            visit(&node_ref.key, &node_ref.value, height)?;
+        }
+        Ok(())
+    }
+
+    // Extend the tower of the bottom level head through every level so that
+    // searches starting from the top level never overshoot a key.
+    fn elect_head(&mut self) {
+        let mut node = match self.levels[0].head.as_ref() {
+            Some(head) => Rc::clone(head),
+            None => return,
+        };
         let mut level = 0;
-        while level < size {
-            println!("printing level {0}", self.levels.len() - level - 1);
-            self.levels[self.levels.len() - level - 1]
-                .iter()
-                .for_each(|node_ref| {
-                    println!("{}", node_ref.borrow().key.clone());
-                });
+        loop {
+            let maybe_up = node.borrow().up.as_ref().and_then(Weak::upgrade);
+            match maybe_up {
+                Some(up) => node = up,
+                None => break,
+            }
             level += 1;
         }
+        let key = node.borrow().key.clone();
+        let value = node.borrow().value.clone();
+        for level in level + 1..self.levels.len() {
+            let new_head =
+                self.levels[level].push_front(self.pool.take(key.clone(), value.clone()));
+            node.borrow_mut().up = Some(Rc::downgrade(&new_head));
+            new_head.borrow_mut().down = Some(node);
+            node = new_head;
+        }
+    }
+
+    // Hand the part of the previous head's tower above the tower of the new head
+    // over to the new head, so that levels headed by the previous head keep
+    // starting with the smallest key. The previous head keeps the levels below,
+    // and a new head is not forced to the full height of the list.
+    fn take_over_head_tower(&mut self, top: &Rc<RefCell<Node<K, V>>>, key: &K, value: &V) {
+        let previous_head = match top.borrow().right.as_ref() {
+            Some(node) => Rc::clone(node),
+            None => return,
+        };
+        let above = match previous_head
+            .borrow_mut()
+            .up
+            .take()
+            .and_then(|up| up.upgrade())
+        {
+            Some(above) => above,
+            None => return,
+        };
+        above.borrow_mut().down = Some(Rc::clone(top));
+        top.borrow_mut().up = Some(Rc::downgrade(&above));
+        let mut maybe_node = Some(above);
+        while let Some(node) = maybe_node {
+            {
+                let mut node_ref = node.borrow_mut();
+                node_ref.key = key.clone();
+                node_ref.value = value.clone();
+            }
+            maybe_node = node.borrow().up.as_ref().and_then(Weak::upgrade);
+        }
+    }
+
+    // Levels above the bottom holding nothing but the head do not shorten any search.
+    fn remove_empty_levels(&mut self) {
+        while self.levels.len() > 1 && self.levels[self.levels.len() - 1].size <= 1 {
+            self.pop_level();
+        }
+    }
+
+    fn pop_level(&mut self) {
+        if let Some(level) = self.levels.pop() {
+            for node in level.iter() {
+                if let Some(down) = node.borrow_mut().down.take() {
+                    down.borrow_mut().up = None;
+                }
+            }
+            self.release_level(level);
+        }
+    }
+
+    // Hand the nodes of a level that is no longer part of the list to the pool.
+    // Links between the level and the one below must already be cut.
+    fn release_level(&mut self, mut level: Level<K, V>) {
+        if self.pool.limit == 0 {
+            return;
+        }
+        for node in level.iter() {
+            node.borrow_mut().left = None;
+        }
+        let mut maybe_node = level.head.take();
+        while let Some(node) = maybe_node {
+            maybe_node = node.borrow_mut().right.take();
+            self.pool.give(node);
+        }
+        level.size = 0;
+    }
+
+    // Number of levels a newly inserted node should span, capped at `max_level`.
+    fn random_height(&mut self) -> usize {
+        tower_height(self.rng.next_u64(), self.probability, self.max_level)
+    }
+
+    // Number of nodes visited while searching for the key from the top of the list.
+    fn search_path_length(&self, key: &K) -> usize {
+        let (mut level, mut node) = match self.head_start(key) {
+            Some(start) => start,
+            None => return 1,
+        };
+        let mut visited = 1;
+        loop {
+            let maybe_next = node.borrow().right.as_ref().map(Rc::clone);
+            match maybe_next {
+                Some(next) if next.borrow().cmp(key) != Ordering::Greater => {
+                    node = next;
+                    visited += 1;
+                    continue;
+                }
+                _ => (),
+            }
+            let maybe_down = node.borrow().down.as_ref().map(Rc::clone);
+            match maybe_down {
+                Some(down) if level > 0 => {
+                    node = down;
+                    level -= 1;
+                    visited += 1;
+                }
+                _ => break,
+            }
+        }
+        visited
     }
 }
 
+impl<K, V> Default for SkipList<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    fn default() -> SkipList<K, V> {
+        SkipList::new()
+    }
+}
+
+// SAFETY: `Rc` is `!Send` because two clones of it living on different threads
+// would update the reference count without synchronization.
+// Every `Rc` and `Weak` pointing at a node is owned by the list, either by its
+// levels, its finger or its node pool, and no method hands one out to the caller.
+// Moving the list therefore moves every handle to its nodes at once and the
+// counts are only ever touched by the thread that owns the list.
+// The list is not `Sync` since methods taking `&self` borrow the `RefCell`s.
+unsafe impl<K: Send, V: Send> Send for SkipList<K, V> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -605,17 +1993,17 @@ mod tests {
     fn test_level() {
         let mut level = Level::new();
         assert_eq!(level.size, 0);
-        level.insert(1, 1);
+        level.insert(detached(1, 1));
         assert_eq!(level.size, 1);
     }
 
     #[test]
     fn test_level_insert() {
         let mut level = Level::new();
-        level.insert(1, "val_1".to_owned());
-        level.insert(4, "val_4".to_owned());
-        level.insert(3, "val_3".to_owned());
-        let node = level.insert(0, "val_0".to_owned());
+        level.insert(detached(1, "val_1".to_owned()));
+        level.insert(detached(4, "val_4".to_owned()));
+        level.insert(detached(3, "val_3".to_owned()));
+        let node = level.insert(detached(0, "val_0".to_owned()));
         assert_eq!(node.borrow().key, 0);
         assert_eq!(level.size, 4);
     }
@@ -623,10 +2011,10 @@ mod tests {
     #[test]
     fn test_level_insert_after() {
         let mut level = Level::new();
-        level.insert(3, 3);
-        level.insert(0, 0);
-        let after = level.insert(1, 1);
-        let new_node = level.insert_after(2, 2, Rc::clone(&after));
+        level.insert(detached(3, 3));
+        level.insert(detached(0, 0));
+        let after = level.insert(detached(1, 1));
+        let new_node = level.insert_after(detached(2, 2), Rc::clone(&after));
         let prev_node = new_node.borrow().left.as_ref().and_then(Weak::upgrade);
         let next_node = new_node.borrow().right.as_ref().map(Rc::clone);
         assert!(prev_node.is_some());
@@ -638,10 +2026,10 @@ mod tests {
     #[test]
     fn test_level_insert_after_tail() {
         let mut level = Level::new();
-        level.insert(3, 3);
-        level.insert(0, 0);
-        let tail = level.insert(5, 5);
-        let new_node = level.insert_after(6, 6, Rc::clone(&tail));
+        level.insert(detached(3, 3));
+        level.insert(detached(0, 0));
+        let tail = level.insert(detached(5, 5));
+        let new_node = level.insert_after(detached(6, 6), Rc::clone(&tail));
         let prev_node = new_node.borrow().left.as_ref().and_then(Weak::upgrade);
         let next_node = new_node.borrow().right.as_ref().map(Rc::clone);
         assert!(prev_node.is_some());
@@ -652,10 +2040,10 @@ mod tests {
     #[test]
     fn test_bisect_after() {
         let mut level = Level::new();
-        level.insert(5, 5);
-        level.insert(2, 2);
-        level.insert(4, 4);
-        let node = level.insert(3, 3);
+        level.insert(detached(5, 5));
+        level.insert(detached(2, 2));
+        level.insert(detached(4, 4));
+        let node = level.insert(detached(3, 3));
         let maybe_found = level.bisect_after(&node, &4);
         assert!(maybe_found.is_some());
         assert_eq!(maybe_found.unwrap().borrow().key, 4);
@@ -667,10 +2055,10 @@ mod tests {
     #[test]
     fn test_bisect_after_larger_node() {
         let mut level = Level::new();
-        level.insert(4, 4);
-        level.insert(2, 2);
-        level.insert(3, 3);
-        let node = level.insert(1, 1);
+        level.insert(detached(4, 4));
+        level.insert(detached(2, 2));
+        level.insert(detached(3, 3));
+        let node = level.insert(detached(1, 1));
         let maybe_found = level.bisect_after(&node, &0);
         assert!(maybe_found.is_none());
     }
@@ -678,10 +2066,10 @@ mod tests {
     #[test]
     fn test_bisect_after_when_node_does_not_exist() {
         let mut level = Level::new();
-        level.insert(4, 4);
-        level.insert(2, 2);
-        level.insert(3, 3);
-        let node = level.insert(1, 1);
+        level.insert(detached(4, 4));
+        level.insert(detached(2, 2));
+        level.insert(detached(3, 3));
+        let node = level.insert(detached(1, 1));
         let maybe_found = level.bisect_after(&node, &5);
         assert!(maybe_found.is_some());
         assert!(maybe_found.as_ref().unwrap().borrow().right.is_none());
@@ -690,11 +2078,11 @@ mod tests {
     #[test]
     fn test_level_is_sorted() {
         let mut level = Level::new();
-        level.insert(1, 1);
-        level.insert(0, 0);
-        level.insert(3, 3);
-        level.insert(2, 2);
-        level.insert(4, 4);
+        level.insert(detached(1, 1));
+        level.insert(detached(0, 0));
+        level.insert(detached(3, 3));
+        level.insert(detached(2, 2));
+        level.insert(detached(4, 4));
         let mut values = vec![];
         level.iter().for_each(|node_ref| {
             let val = node_ref.borrow().key;
@@ -712,12 +2100,12 @@ mod tests {
     #[test]
     fn test_bisect_when_key_exists() {
         let mut level = Level::new();
-        level.insert(1, 1);
-        level.insert(0, 0);
-        level.insert(3, 3);
-        level.insert(2, 2);
-        level.insert(2, 2);
-        level.insert(4, 4);
+        level.insert(detached(1, 1));
+        level.insert(detached(0, 0));
+        level.insert(detached(3, 3));
+        level.insert(detached(2, 2));
+        level.insert(detached(2, 2));
+        level.insert(detached(4, 4));
         // test value exists in middle
         let maybe_marker = level.bisect(&2);
         assert!(maybe_marker.is_some());
@@ -739,12 +2127,12 @@ mod tests {
     #[test]
     fn test_bisect_when_key_does_not_exist() {
         let mut level = Level::new();
-        level.insert(1, 1);
-        level.insert(0, 0);
-        level.insert(3, 3);
-        level.insert(2, 2);
-        level.insert(2, 2);
-        level.insert(5, 5);
+        level.insert(detached(1, 1));
+        level.insert(detached(0, 0));
+        level.insert(detached(3, 3));
+        level.insert(detached(2, 2));
+        level.insert(detached(2, 2));
+        level.insert(detached(5, 5));
         // test value doesn't exist
         let maybe_marker = level.bisect(&4);
         assert!(maybe_marker.is_some());
@@ -757,12 +2145,12 @@ mod tests {
     #[test]
     fn test_bisect_after_with_last_node() {
         let mut level: Level<i32, i32> = Level::new();
-        level.insert(1, 1);
-        level.insert(0, 0);
-        level.insert(3, 3);
-        level.insert(2, 2);
-        level.insert(2, 2);
-        let last_node = level.insert(5, 5);
+        level.insert(detached(1, 1));
+        level.insert(detached(0, 0));
+        level.insert(detached(3, 3));
+        level.insert(detached(2, 2));
+        level.insert(detached(2, 2));
+        let last_node = level.insert(detached(5, 5));
         assert!(last_node.borrow().right.is_none());
         let maybe_found = level.bisect_after(&last_node, &5);
         assert!(maybe_found.is_some());
@@ -775,11 +2163,11 @@ mod tests {
     #[test]
     fn test_bisect_after_when_insertion_point_is_at_end() {
         let mut level: Level<i32, i32> = Level::new();
-        level.insert(1, 1);
-        level.insert(0, 0);
-        level.insert(3, 3);
-        level.insert(2, 2);
-        let node = level.insert(2, 2);
+        level.insert(detached(1, 1));
+        level.insert(detached(0, 0));
+        level.insert(detached(3, 3));
+        level.insert(detached(2, 2));
+        let node = level.insert(detached(2, 2));
         let maybe_insert = level.bisect_after(&node, &5);
         assert!(maybe_insert.is_some());
         assert_eq!(maybe_insert.as_ref().unwrap().borrow().key, 3);
@@ -788,20 +2176,20 @@ mod tests {
     #[test]
     fn test_delete_from_level() {
         let mut level = Level::new();
-        level.insert(1, 1);
-        level.insert(0, 0);
-        level.insert(3, 3);
-        level.insert(2, 2);
-        level.insert(2, 2);
-        level.insert(6, 6);
-        level.insert(4, 4);
-        level.insert(4, 4);
+        level.insert(detached(1, 1));
+        level.insert(detached(0, 0));
+        level.insert(detached(3, 3));
+        level.insert(detached(2, 2));
+        level.insert(detached(2, 2));
+        level.insert(detached(6, 6));
+        level.insert(detached(4, 4));
+        level.insert(detached(4, 4));
         // delete value from middle of list
-        level.delete(&2);
+        unlink_key(&mut level, &2);
         // delete from end of last
-        level.delete(&6);
+        unlink_key(&mut level, &6);
         // delete from start of list
-        level.delete(&0);
+        unlink_key(&mut level, &0);
         let mut values = vec![];
         level.iter().for_each(|node_ref| {
             let value = node_ref.borrow().key;
@@ -810,11 +2198,32 @@ mod tests {
         assert_eq!(level.size, 5);
         assert_eq!(values, vec![1, 2, 3, 4, 4]);
         let mut new_level = Level::new();
-        new_level.insert(0, 0);
-        new_level.delete(&0);
+        new_level.insert(detached(0, 0));
+        unlink_key(&mut new_level, &0);
         assert_eq!(new_level.size, 0);
     }
 
+    fn detached<K: Ord + Clone, V: Clone>(key: K, value: V) -> Rc<RefCell<Node<K, V>>> {
+        Rc::new(RefCell::new(Node::new(key, value)))
+    }
+
+    fn unlink_key(level: &mut Level<i32, i32>, key: &i32) {
+        let node = level.iter().find(|node| node.borrow().key == *key).unwrap();
+        level.unlink(&node);
+    }
+
+    #[test]
+    fn test_level_push_front() {
+        let mut level = Level::new();
+        level.insert(detached(1, 1));
+        level.insert(detached(2, 2));
+        let head = level.push_front(detached(1, 10));
+        assert!(head.borrow().left.is_none());
+        assert_eq!(level.size, 3);
+        let values: Vec<i32> = level.iter().map(|node| node.borrow().value).collect();
+        assert_eq!(values, vec![10, 1, 2]);
+    }
+
     #[test]
     fn test_skiplist() {
         let list: SkipList<i32, i32> = SkipList::new();
@@ -887,4 +2296,677 @@ mod tests {
         let values: Vec<i32> = list.collect().iter().map(|tup| tup.1).collect();
         assert_eq!(values, vec![2, 3, 5, 6, 7, 8]);
     }
+
+    #[test]
+    fn test_builder_defaults() {
+        let list: SkipList<i32, i32> = SkipListBuilder::new().build();
+        assert_eq!(list.probability(), DEFAULT_PROBABILITY);
+        assert_eq!(list.max_level(), DEFAULT_MAX_LEVEL);
+    }
+
+    #[test]
+    fn test_builder_max_level_caps_levels() {
+        let mut list = SkipListBuilder::new().probability(0.9).max_level(3).build();
+        for i in 0..500 {
+            list.insert(i, i);
+        }
+        assert!(list.levels.len() <= 3);
+        assert_eq!(list.len(), 500);
+        assert_eq!(list.get(&250), Some(250));
+    }
+
+    #[test]
+    fn test_builder_expected_capacity() {
+        let list: SkipList<i32, i32> = SkipListBuilder::new()
+            .probability(0.25)
+            .expected_capacity(1 << 20)
+            .build();
+        assert_eq!(list.max_level(), 10);
+        let list: SkipList<i32, i32> = SkipListBuilder::new()
+            .expected_capacity(1 << 20)
+            .max_level(4)
+            .build();
+        assert_eq!(list.max_level(), 4);
+        let list: SkipList<i32, i32> = SkipListBuilder::new().expected_capacity(0).build();
+        assert_eq!(list.max_level(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_builder_rejects_invalid_probability() {
+        SkipListBuilder::new().probability(1.0);
+    }
+
+    #[test]
+    fn test_single_level_list() {
+        let mut list = SkipListBuilder::new().max_level(1).build();
+        list.insert(3, 3);
+        list.insert(1, 1);
+        list.insert(2, 2);
+        assert_eq!(list.levels.len(), 1);
+        assert_eq!(list.get(&2), Some(2));
+        assert_eq!(list.collect(), vec![(1, 1), (2, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn test_seeded_lists_have_identical_structure() {
+        let mut first = SkipListBuilder::new().seed(7).build();
+        let mut second = SkipListBuilder::new().seed(7).build();
+        for i in 0..200 {
+            first.insert(i, i);
+            second.insert(i, i);
+        }
+        let level_sizes = |list: &SkipList<i32, i32>| -> Vec<usize> {
+            list.levels.iter().map(|level| level.size).collect()
+        };
+        assert!(first.levels.len() > 1);
+        assert_eq!(level_sizes(&first), level_sizes(&second));
+    }
+
+    #[test]
+    fn test_custom_rng() {
+        let mut list = SkipListBuilder::new()
+            .rng(crate::rng::SplitMix64::new(3))
+            .build();
+        for i in (0..50).rev() {
+            list.insert(i, i * 2);
+        }
+        assert_eq!(list.get(&21), Some(42));
+        assert_eq!(list.len(), 50);
+    }
+
+    #[test]
+    fn test_finger_append_starts_at_bottom_level() {
+        let mut list = SkipListBuilder::new().seed(11).finger(true).build();
+        for i in 0..1000 {
+            list.insert(i, i);
+        }
+        let (level, node) = list.finger_start(&1000).unwrap();
+        assert_eq!(level, 0);
+        assert_eq!(node.borrow().key, 999);
+        assert_eq!(list.len(), 1000);
+        assert_eq!(list.get(&500), Some(500));
+        assert_eq!(list.get(&1000), None);
+    }
+
+    #[test]
+    fn test_finger_matches_plain_list() {
+        let mut rng = crate::rng::SplitMix64::new(5);
+        let mut with_finger = SkipListBuilder::new().seed(1).finger(true).build();
+        let mut plain = SkipListBuilder::new().seed(1).build();
+        for step in 0..2000 {
+            let key = (rng.next_u64() % 300) as i32;
+            match step % 4 {
+                0 | 1 => {
+                    if with_finger.get(&key).is_none() {
+                        with_finger.insert(key, step);
+                        plain.insert(key, step);
+                    }
+                }
+                2 => assert_eq!(with_finger.get(&key), plain.get(&key)),
+                _ => {
+                    with_finger.delete(&key);
+                    plain.delete(&key);
+                }
+            }
+        }
+        assert_eq!(with_finger.collect(), plain.collect());
+        assert_eq!(with_finger.len(), plain.len());
+    }
+
+    #[test]
+    fn test_finger_moves_backwards() {
+        let mut list = SkipList::with_finger();
+        for i in (0..100).map(|i| i * 2) {
+            list.insert(i, i);
+        }
+        assert_eq!(list.get(&150), Some(150));
+        assert_eq!(list.get(&3), None);
+        assert_eq!(list.get(&4), Some(4));
+        list.insert(-1, -1);
+        assert_eq!(list.get(&-1), Some(-1));
+        assert_eq!(list.get(&198), Some(198));
+        assert_eq!(list.collect()[..3], [(-1, -1), (0, 0), (2, 2)]);
+    }
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<SkipList<i32, String>>();
+        assert_send::<SkipListBuilder>();
+    }
+
+    #[test]
+    fn test_move_list_between_threads() {
+        let loader = std::thread::spawn(|| {
+            let mut list = SkipListBuilder::new().finger(true).build();
+            for i in 0..1000 {
+                list.insert(i, i.to_string());
+            }
+            list
+        });
+        let mut list = loader.join().unwrap();
+        let worker = std::thread::spawn(move || {
+            list.delete(&10);
+            (list.get(&999), list.get(&10), list.len())
+        });
+        assert_eq!(worker.join().unwrap(), (Some("999".to_string()), None, 999));
+    }
+
+    fn total_nodes<K: Ord + Clone, V: Clone>(list: &SkipList<K, V>) -> usize {
+        list.levels.iter().map(|level| level.size).sum()
+    }
+
+    #[test]
+    fn test_with_capacity_preallocates_nodes() {
+        let mut list: SkipList<u64, u64> = SkipList::with_capacity(100);
+        let preallocated = list.pool.free.len();
+        assert!(preallocated >= 200);
+        for i in 0..100 {
+            list.insert(i, i);
+        }
+        assert_eq!(list.collect(), (0..100).map(|i| (i, i)).collect::<Vec<_>>());
+        // towers are random, so an unlucky list may need more nodes than the
+        // pool holds and allocate the rest
+        assert_eq!(
+            list.pool.free.len(),
+            preallocated.saturating_sub(total_nodes(&list))
+        );
+    }
+
+    // Counts the allocations of threads that opted in, so that tests running
+    // in parallel do not disturb each other.
+    struct CountingAllocator;
+
+    std::thread_local! {
+        static ALLOCATIONS: core::cell::Cell<Option<usize>> = const { core::cell::Cell::new(None) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get().map(|count| count + 1)));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn count_allocations<F: FnOnce()>(run: F) -> usize {
+        ALLOCATIONS.with(|count| count.set(Some(0)));
+        run();
+        ALLOCATIONS.with(|count| count.take()).unwrap_or(0)
+    }
+
+    #[test]
+    fn test_with_capacity_steady_state_does_not_allocate() {
+        let mut list: SkipList<u64, u64> = SkipList::with_capacity(1000);
+        for i in 0..500 {
+            list.insert(i * 2, i);
+        }
+        let mut rng = crate::rng::SplitMix64::new(3);
+        let allocations = count_allocations(|| {
+            for _ in 0..10_000 {
+                let key = rng.next_u64() % 500 * 2;
+                list.delete(&key);
+                list.insert(key, key);
+            }
+        });
+        assert_eq!(allocations, 0);
+        assert_eq!(list.len(), 500);
+        let mut unpooled: SkipList<u64, u64> = SkipList::new();
+        unpooled.insert(0, 0);
+        assert!(count_allocations(|| unpooled.insert(1, 1)) > 0);
+    }
+
+    #[test]
+    fn test_node_pool_drops_pooled_entries() {
+        let value = Rc::new(());
+        let mut list = SkipListBuilder::new().node_pool(100).build();
+        for i in 0..10 {
+            list.insert(i, Rc::clone(&value));
+        }
+        list.clear();
+        assert!(!list.pool.free.is_empty());
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_node_pool_reuses_deleted_nodes() {
+        let mut list = SkipListBuilder::new().seed(5).node_pool(1000).build();
+        for i in 0..100 {
+            list.insert(i, i);
+        }
+        let allocated = total_nodes(&list);
+        let mut rng = crate::rng::SplitMix64::new(9);
+        for _ in 0..1000 {
+            let key = rng.next_u64() % 100;
+            list.delete(&key);
+            list.insert(key, key);
+            // every node is either linked or pooled unless the pool ran dry
+            assert!(total_nodes(&list) + list.pool.free.len() >= allocated);
+        }
+        let linked = total_nodes(&list);
+        let pooled = list.pool.free.len();
+        for i in 0..100 {
+            list.delete(&i);
+        }
+        assert!(list.is_empty());
+        assert_eq!(list.pool.free.len(), linked + pooled - total_nodes(&list));
+        for i in 0..100 {
+            list.insert(i, i);
+        }
+        assert_eq!(list.get(&42), Some(42));
+    }
+
+    #[test]
+    fn test_clear_returns_nodes_to_pool() {
+        let mut list = SkipListBuilder::new().seed(5).node_pool(10_000).build();
+        for i in 0..1000 {
+            list.insert(i, i);
+        }
+        let linked = total_nodes(&list);
+        list.clear();
+        assert_eq!(list.pool.free.len(), linked);
+        let mut limited = SkipListBuilder::new().node_pool(10).build();
+        for i in 0..1000 {
+            limited.insert(i, i);
+        }
+        limited.clear();
+        assert_eq!(limited.pool.free.len(), 10);
+    }
+
+    #[test]
+    fn test_try_insert_capacity_limit() {
+        let mut list = SkipListBuilder::new().seed(9).capacity_limit(100).build();
+        assert_eq!(list.capacity_limit(), Some(100));
+        for i in 0..100 {
+            assert_eq!(list.try_insert(i, i), Ok(()));
+        }
+        assert_eq!(
+            list.try_insert(100, 100),
+            Err(SkipListError::CapacityExceeded { capacity: 100 })
+        );
+        assert_eq!(list.len(), 100);
+        assert_eq!(list.get(&100), None);
+        // deleting entries makes room again
+        list.delete(&0);
+        assert_eq!(list.try_insert(100, 100), Ok(()));
+        assert_eq!(list.get(&100), Some(100));
+        assert_eq!(list.len(), 100);
+    }
+
+    #[test]
+    fn test_try_insert_unique() {
+        let mut list = SkipListBuilder::new().seed(4).unique(true).build();
+        for i in (0..50).rev() {
+            assert_eq!(list.try_insert(i * 2, i), Ok(()));
+        }
+        for i in 0..50 {
+            assert_eq!(list.try_insert(i * 2, 0), Err(SkipListError::DuplicateKey));
+        }
+        assert_eq!(list.len(), 50);
+        assert_eq!(list.get(&0), Some(0));
+        assert_eq!(list.get(&98), Some(49));
+        assert_eq!(list.try_insert(99, 0), Ok(()));
+        // duplicates are accepted unless the list is unique
+        let mut list = SkipList::new();
+        assert_eq!(list.try_insert(1, 1), Ok(()));
+        assert_eq!(list.try_insert(1, 2), Ok(()));
+        assert_eq!(list.len(), 2);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let mut list: SkipList<u32, String> = SkipListBuilder::new().seed(2).build_measured();
+        let empty = list.memory_usage();
+        for i in 0..1000 {
+            list.insert(i, "x".repeat(100));
+        }
+        let full = list.memory_usage();
+        assert_eq!(list.heap_bytes, 100_000);
+        assert!(full >= empty + 100_000 + 1000 * mem::size_of::<Node<u32, String>>());
+        // upper levels hold copies of the entries
+        assert!(full <= empty + 300_000 + 3000 * node_size::<u32, String>());
+        for i in 0..500 {
+            list.delete(&i);
+        }
+        assert_eq!(list.heap_bytes, 50_000);
+        assert!(list.memory_usage() < full);
+        list.clear();
+        assert_eq!(list.heap_bytes, 0);
+        // without measuring only the nodes are counted
+        let mut unmeasured: SkipList<u32, String> = SkipListBuilder::new().build();
+        unmeasured.insert(1, "x".repeat(100));
+        assert_eq!(unmeasured.heap_bytes, 0);
+    }
+
+    #[test]
+    fn test_try_insert_memory_limit() {
+        let limit = 64 * 1024;
+        let mut list: SkipList<u64, Vec<u8>> = SkipListBuilder::new()
+            .seed(8)
+            .memory_limit(limit)
+            .build_measured();
+        assert_eq!(list.memory_limit(), Some(limit));
+        let mut key = 0;
+        let error = loop {
+            match list.try_insert(key, vec![0; 1024]) {
+                Ok(()) => key += 1,
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(error, SkipListError::MemoryLimitExceeded { limit });
+        assert!(key > 0 && key < 64);
+        assert_eq!(list.len(), key as usize);
+        assert!(list.memory_usage() <= limit + 4 * node_size::<u64, Vec<u8>>() + 4096);
+        // deleting entries makes room again
+        for key in 1..5 {
+            list.delete(&key);
+        }
+        assert_eq!(list.try_insert(key, vec![0; 1024]), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity limit is 1 entries")]
+    fn test_insert_panics_over_capacity_limit() {
+        let mut list = SkipListBuilder::new().capacity_limit(1).build();
+        list.insert(1, 1);
+        list.insert(2, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "memory limit is 4096 bytes")]
+    fn test_insert_panics_over_memory_limit() {
+        let mut list: SkipList<u64, Vec<u8>> =
+            SkipListBuilder::new().memory_limit(4096).build_measured();
+        for key in 0..8 {
+            list.insert(key, vec![0; 1024]);
+        }
+    }
+
+    #[test]
+    fn test_drop_long_level() {
+        let mut level = Level::new();
+        let mut tail = level.insert(detached(0, 0));
+        for i in 1..500_000 {
+            tail = level.insert_after(detached(i, i), tail);
+        }
+        assert_eq!(level.size, 500_000);
+        drop(tail);
+        drop(level);
+    }
+
+    #[test]
+    fn test_drop_long_list() {
+        let mut list = SkipListBuilder::new().seed(3).finger(true).build();
+        for i in 0..200_000 {
+            list.insert(i, i);
+        }
+        assert_eq!(list.len(), 200_000);
+        drop(list);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut list = SkipListBuilder::new().seed(9).finger(true).build();
+        for i in 0..1000 {
+            list.insert(i, i);
+        }
+        list.clear();
+        assert!(list.is_empty());
+        assert_eq!(list.levels.len(), 1);
+        assert_eq!(list.get(&10), None);
+        assert!(list.collect().is_empty());
+        list.insert(2, 2);
+        list.insert(1, 1);
+        assert_eq!(list.collect(), vec![(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_stats_empty_list() {
+        let list: SkipList<i32, i32> = SkipList::new();
+        let stats = list.stats();
+        assert_eq!(stats.levels, 1);
+        assert_eq!(stats.nodes_per_level, vec![0]);
+        assert_eq!(stats.max_height, 0);
+        assert_eq!(stats.average_height, 0.0);
+        assert_eq!(stats.average_search_path, 0.0);
+    }
+
+    #[test]
+    fn test_stats_single_level() {
+        let mut list = SkipListBuilder::new().max_level(1).build();
+        for i in 0..10 {
+            list.insert(i, i);
+        }
+        let stats = list.stats();
+        assert_eq!(stats.levels, 1);
+        assert_eq!(stats.nodes_per_level, vec![10]);
+        assert_eq!(stats.max_height, 1);
+        assert_eq!(stats.average_height, 1.0);
+        // searching for the i-th key visits i + 1 nodes
+        assert_eq!(stats.average_search_path, 5.5);
+        assert!(stats.memory_bytes >= 10 * mem::size_of::<Node<i32, i32>>());
+    }
+
+    #[test]
+    fn test_stats_multiple_levels() {
+        let mut list = SkipListBuilder::new().seed(21).build();
+        for i in 0..10_000 {
+            list.insert(i, i);
+        }
+        let stats = list.stats();
+        assert_eq!(stats.levels, list.levels.len());
+        assert_eq!(stats.nodes_per_level[0], 10_000);
+        assert_eq!(stats.max_height, stats.levels);
+        assert!(stats.average_height > 1.5 && stats.average_height < 2.5);
+        assert!(stats.average_search_path < 100.0);
+    }
+
+    #[test]
+    fn test_descending_inserts_grow_levels() {
+        let mut list = SkipListBuilder::new().seed(5).build();
+        for i in (0..2000).rev() {
+            list.insert(i, i);
+        }
+        let stats = list.stats();
+        assert!(stats.levels > 5, "{} levels", stats.levels);
+        assert!(stats.average_height < 2.5);
+        assert!(stats.average_search_path < 60.0);
+        let head_key = list.levels[0].head.as_ref().unwrap().borrow().key;
+        assert_eq!(head_key, 0);
+        for level in list.levels.iter() {
+            assert_eq!(level.head.as_ref().unwrap().borrow().key, head_key);
+        }
+        for i in 0..2000 {
+            assert_eq!(list.get(&i), Some(i));
+        }
+        assert_eq!(
+            list.collect(),
+            (0..2000).map(|i| (i, i)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_range_seeks_to_start() {
+        let mut list = SkipListBuilder::new().seed(11).build();
+        for i in (0..500).rev() {
+            list.insert(i * 2, i);
+        }
+        list.insert(100, 0);
+        let all = list.collect();
+        let expect = |start: Bound<i32>, end: Bound<i32>| -> Vec<(i32, i32)> {
+            all.iter()
+                .filter(|(key, _)| (start, end).contains(key))
+                .cloned()
+                .collect()
+        };
+        for (start, end) in [
+            (Bound::Included(100), Bound::Excluded(110)),
+            (Bound::Excluded(100), Bound::Included(110)),
+            (Bound::Included(99), Bound::Unbounded),
+            (Bound::Unbounded, Bound::Excluded(0)),
+            (Bound::Excluded(998), Bound::Unbounded),
+            (Bound::Included(-5), Bound::Included(3)),
+        ] {
+            assert_eq!(
+                list.range((start, end)).collect::<Vec<_>>(),
+                expect(start, end)
+            );
+        }
+        // both entries with the duplicate key are part of the range
+        assert_eq!(list.range(100..=100).count(), 2);
+        assert_eq!(SkipList::<i32, i32>::new().range(..).count(), 0);
+    }
+
+    #[test]
+    fn test_upsert_replaces_every_copy() {
+        let mut list: SkipList<u64, String> = SkipListBuilder::new().seed(3).build_measured();
+        for i in 0..200 {
+            assert_eq!(list.upsert(i, "x".repeat(10)), None);
+        }
+        for i in 0..200 {
+            assert_eq!(list.upsert(i, "y".repeat(20)), Some("x".repeat(10)));
+        }
+        assert_eq!(list.len(), 200);
+        assert_eq!(list.heap_bytes, 200 * 20);
+        for level in list.levels.iter() {
+            assert!(level
+                .iter()
+                .all(|node| node.borrow().value == "y".repeat(20)));
+        }
+    }
+
+    #[test]
+    fn test_delete_head_keeps_list_searchable() {
+        let mut list = SkipListBuilder::new().seed(13).build();
+        for i in 0..500 {
+            list.insert(i, i);
+        }
+        for i in 0..499 {
+            list.delete(&i);
+            assert_eq!(list.get(&(i + 1)), Some(i + 1));
+            assert_eq!(list.bisect(&(i + 1)), Some(i + 1));
+            let head_key = list.levels[0].head.as_ref().unwrap().borrow().key;
+            for level in list.levels.iter() {
+                assert_eq!(level.head.as_ref().unwrap().borrow().key, head_key);
+            }
+        }
+        assert_eq!(list.levels.len(), 1);
+        list.delete(&499);
+        assert!(list.is_empty());
+        assert_eq!(list.levels.len(), 1);
+        list.insert(3, 3);
+        assert_eq!(list.get(&3), Some(3));
+    }
+
+    #[test]
+    fn test_delete_matches_model_with_duplicates() {
+        let mut rng = crate::rng::SplitMix64::new(17);
+        let mut list = SkipListBuilder::new().seed(2).build();
+        let mut model: Vec<(i32, usize)> = Vec::new();
+        for step in 0..3000 {
+            let key = (rng.next_u64() % 100) as i32;
+            let last_equal = model.iter().rposition(|entry| entry.0 == key);
+            if step % 3 == 0 {
+                list.delete(&key);
+                if let Some(position) = last_equal {
+                    model.remove(position);
+                }
+            } else {
+                list.insert(key, step);
+                let position = model.iter().position(|entry| entry.0 > key);
+                model.insert(position.unwrap_or(model.len()), (key, step));
+            }
+            let expected = model
+                .iter()
+                .rev()
+                .find(|entry| entry.0 == key)
+                .map(|entry| entry.1);
+            assert_eq!(list.get(&key), expected);
+        }
+        assert_eq!(list.collect(), model);
+        assert_eq!(list.len(), model.len());
+        let stats = list.stats();
+        for level in 1..stats.levels {
+            assert!(stats.nodes_per_level[level] <= stats.nodes_per_level[level - 1]);
+        }
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut list = SkipListBuilder::new().seed(4).finger(true).build();
+        for i in 0..10_000 {
+            list.insert(i, i);
+        }
+        let levels_before = list.levels.len();
+        for i in 10..10_000 {
+            list.delete(&i);
+        }
+        list.shrink_to_fit();
+        assert!(list.levels.len() <= level_for_capacity(10, DEFAULT_PROBABILITY));
+        assert!(list.levels.len() < levels_before);
+        assert_eq!(list.collect(), (0..10).map(|i| (i, i)).collect::<Vec<_>>());
+        for i in 0..10 {
+            assert_eq!(list.get(&i), Some(i));
+        }
+        list.insert(20, 20);
+        assert_eq!(list.get(&20), Some(20));
+    }
+
+    #[test]
+    fn test_ideal_height() {
+        assert_eq!(ideal_height(0, 2, 32), 1);
+        assert_eq!(ideal_height(1, 2, 32), 1);
+        assert_eq!(ideal_height(8, 2, 32), 4);
+        assert_eq!(ideal_height(12, 2, 32), 3);
+        assert_eq!(ideal_height(16, 4, 32), 3);
+        assert_eq!(ideal_height(1024, 2, 4), 4);
+    }
+
+    #[test]
+    fn test_rebalance_ideal() {
+        let mut list = SkipListBuilder::new().seed(8).build();
+        for i in (0..1024).rev() {
+            list.insert(i, i * 10);
+        }
+        list.rebalance(RebalanceStrategy::Ideal);
+        let stats = list.stats();
+        let expected: Vec<usize> = (0..10).map(|level| 1024 >> level).collect();
+        assert_eq!(stats.nodes_per_level, expected);
+        assert_eq!(list.len(), 1024);
+        assert_eq!(list.get(&777), Some(7770));
+        assert_eq!(
+            list.collect(),
+            (0..1024).map(|i| (i, i * 10)).collect::<Vec<_>>()
+        );
+        list.insert(2000, 1);
+        list.delete(&0);
+        assert_eq!(list.get(&2000), Some(1));
+        assert_eq!(list.get(&1), Some(10));
+    }
+
+    #[test]
+    fn test_rebalance_randomized() {
+        let mut list = SkipListBuilder::new().seed(8).finger(true).build();
+        for i in 0..2000 {
+            list.insert(i % 500, i);
+        }
+        let before = list.collect();
+        list.rebalance(RebalanceStrategy::Randomized);
+        assert_eq!(list.collect(), before);
+        assert_eq!(list.len(), 2000);
+        assert_eq!(list.get(&499), Some(1999));
+        let stats = list.stats();
+        assert!(stats.average_height > 1.5 && stats.average_height < 2.5);
+        let mut empty: SkipList<i32, i32> = SkipList::new();
+        empty.rebalance(RebalanceStrategy::Ideal);
+        assert!(empty.is_empty());
+    }
 }
@@ -0,0 +1,304 @@
+use crate::persistent::PersistentSkipList;
+use std::ops::RangeBounds;
+use std::sync::{Arc, PoisonError, RwLock};
+
+pub use crate::persistent::Iter;
+
+/// Skip list with a single writer whose readers take point-in-time snapshots.
+///
+/// Every change is made on a copy of the path to the affected key, so a
+/// [`Snapshot`] keeps seeing the list as it was when it was taken while the
+/// writer moves on.
+/// Snapshots share all untouched nodes with the list and with each other,
+/// and they never block the writer.
+/// Keys are unique, inserting an existing key replaces its value.
+///
+/// # Example
+/// ```rust
+/// use subway::snapshot::SnapshotSkipList;
+///
+/// let mut list = SnapshotSkipList::new();
+/// list.insert(1, "one");
+/// let snapshot = list.snapshot();
+/// list.insert(2, "two");
+/// list.remove(&1);
+/// assert_eq!(snapshot.get(&1), Some(&"one"));
+/// assert_eq!(snapshot.len(), 1);
+/// assert_eq!(list.snapshot().len(), 1);
+/// ```
+pub struct SnapshotSkipList<K, V> {
+    list: PersistentSkipList<K, V>,
+    // latest version handed to readers on other threads
+    published: Arc<RwLock<PersistentSkipList<K, V>>>,
+}
+
+impl<K, V> SnapshotSkipList<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    /// Create an empty list.
+    pub fn new() -> SnapshotSkipList<K, V> {
+        SnapshotSkipList {
+            list: PersistentSkipList::new(),
+            published: Arc::new(RwLock::new(PersistentSkipList::new())),
+        }
+    }
+
+    /// Insert the given key and value into the list.
+    ///
+    /// # Returns
+    /// The value previously associated with the key, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let previous = self.list.insert_in_place(key, value);
+        self.publish();
+        previous
+    }
+
+    /// Remove the key from the list.
+    ///
+    /// # Returns
+    /// The value associated with the key, if any.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.list.remove_in_place(key)?;
+        self.publish();
+        Some(value)
+    }
+
+    /// Get the value associated with a key if it exists.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.list.get(key)
+    }
+
+    /// Returns the number of entries in the list.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Returns whether list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Take a snapshot of the current contents of the list.
+    pub fn snapshot(&self) -> Snapshot<K, V> {
+        Snapshot {
+            list: self.list.clone(),
+        }
+    }
+
+    /// Create a handle from which other threads take snapshots.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::thread;
+    /// use subway::snapshot::SnapshotSkipList;
+    ///
+    /// let mut list = SnapshotSkipList::new();
+    /// let reader = list.reader();
+    /// let analytics = thread::spawn(move || {
+    ///     let snapshot = reader.snapshot();
+    ///     snapshot.iter().count()
+    /// });
+    /// for i in 0..1000 {
+    ///     list.insert(i, i);
+    /// }
+    /// assert!(analytics.join().unwrap() <= 1000);
+    /// ```
+    pub fn reader(&self) -> SnapshotReader<K, V> {
+        SnapshotReader {
+            published: Arc::clone(&self.published),
+        }
+    }
+
+    // Readers only hold the lock for as long as it takes to clone a pointer.
+    fn publish(&mut self) {
+        let mut published = self
+            .published
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        *published = self.list.clone();
+    }
+}
+
+impl<K, V> Default for SnapshotSkipList<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    fn default() -> SnapshotSkipList<K, V> {
+        SnapshotSkipList::new()
+    }
+}
+
+/// Handle through which readers on other threads take snapshots of a
+/// [`SnapshotSkipList`].
+pub struct SnapshotReader<K, V> {
+    published: Arc<RwLock<PersistentSkipList<K, V>>>,
+}
+
+impl<K, V> SnapshotReader<K, V> {
+    /// Take a snapshot of the contents of the list after its latest change.
+    pub fn snapshot(&self) -> Snapshot<K, V> {
+        let published = self
+            .published
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        Snapshot {
+            list: published.clone(),
+        }
+    }
+}
+
+impl<K, V> Clone for SnapshotReader<K, V> {
+    fn clone(&self) -> SnapshotReader<K, V> {
+        SnapshotReader {
+            published: Arc::clone(&self.published),
+        }
+    }
+}
+
+/// Immutable view of a [`SnapshotSkipList`] at the time it was taken.
+/// Cloning a snapshot is cheap.
+pub struct Snapshot<K, V> {
+    list: PersistentSkipList<K, V>,
+}
+
+impl<K, V> Snapshot<K, V>
+where
+    K: Ord,
+{
+    /// Get the value associated with a key if it exists.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.list.get(key)
+    }
+
+    /// Iterate over the entries whose keys fall in the given range.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::snapshot::SnapshotSkipList;
+    ///
+    /// let mut list = SnapshotSkipList::new();
+    /// for i in 0..10 {
+    ///     list.insert(i, i * 10);
+    /// }
+    /// let snapshot = list.snapshot();
+    /// let values: Vec<&i32> = snapshot.range(3..6).map(|(_, value)| value).collect();
+    /// assert_eq!(values, vec![&30, &40, &50]);
+    /// ```
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V>
+    where
+        K: Clone,
+    {
+        self.list.range(range)
+    }
+
+    /// Iterate over all entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V>
+    where
+        K: Clone,
+    {
+        self.list.iter()
+    }
+
+    /// Returns the number of entries in the snapshot.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Returns whether snapshot is empty.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl<K, V> Clone for Snapshot<K, V> {
+    fn clone(&self) -> Snapshot<K, V> {
+        Snapshot {
+            list: self.list.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_send_sync;
+    use std::thread;
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send_sync::<Snapshot<u64, String>>();
+        assert_send_sync::<SnapshotReader<u64, String>>();
+        assert_send_sync::<SnapshotSkipList<u64, String>>();
+    }
+
+    #[test]
+    fn test_operations() {
+        let mut list = SnapshotSkipList::new();
+        assert!(list.is_empty());
+        for i in (0..100).rev() {
+            assert_eq!(list.insert(i, i as usize), None);
+        }
+        assert_eq!(list.insert(5, 0), Some(5));
+        assert_eq!(list.get(&5), Some(&0));
+        assert_eq!(list.remove(&5), Some(0));
+        assert_eq!(list.remove(&5), None);
+        assert_eq!(list.get(&5), None);
+        assert_eq!(list.len(), 99);
+    }
+
+    #[test]
+    fn test_snapshot_is_isolated() {
+        let mut list = SnapshotSkipList::new();
+        for i in 0..100 {
+            list.insert(i, i as usize);
+        }
+        let before = list.snapshot();
+        for i in 0..100 {
+            if i % 3 == 0 {
+                list.remove(&i);
+            } else {
+                list.insert(i, 0);
+            }
+        }
+        list.insert(1000, 1000);
+        assert_eq!(before.len(), 100);
+        assert_eq!(
+            before
+                .iter()
+                .map(|(key, value)| (*key, *value))
+                .collect::<Vec<_>>(),
+            (0..100).map(|i| (i, i as usize)).collect::<Vec<_>>()
+        );
+        let after = list.snapshot();
+        assert_eq!(after.get(&3), None);
+        assert_eq!(after.get(&4), Some(&0));
+        assert_eq!(after.get(&1000), Some(&1000));
+    }
+
+    #[test]
+    fn test_readers_on_other_threads() {
+        let mut list = SnapshotSkipList::new();
+        let reader = list.reader();
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                let reader = reader.clone();
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        let snapshot = reader.snapshot();
+                        let keys: Vec<&i32> = snapshot.iter().map(|(key, _)| key).collect();
+                        // every version holds a prefix of the inserted keys
+                        assert_eq!(keys.len(), snapshot.len());
+                        assert!(keys.iter().enumerate().all(|(i, key)| **key == i as i32));
+                    }
+                });
+            }
+            for i in 0..2000 {
+                list.insert(i, i as usize);
+            }
+        });
+        assert_eq!(reader.snapshot().len(), 2000);
+    }
+}
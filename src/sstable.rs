@@ -0,0 +1,683 @@
+//! Sorted string tables: immutable on-disk files holding entries in key order,
+//! available with the `std` feature.
+//!
+//! A table is written once from a sorted source such as a [`SkipList`](crate::skiplist::SkipList)
+//! or a [`FrozenMemtable`](crate::memtable::FrozenMemtable) and read through a sparse index,
+//! so that lookups and range scans only read the blocks they need.
+//!
+//! # Format
+//! All integers are little endian and keys and values are encoded through
+//! [`Codec`], each prefixed by a 4 byte length.
+//!
+//! | Section      | Description                                                          |
+//! |--------------|----------------------------------------------------------------------|
+//! | data blocks  | consecutive entries, each block followed by its CRC-32               |
+//! | index block  | first key, 8 byte offset and 4 byte length of every data block, followed by its CRC-32 |
+//! | footer       | 8 byte index offset, 8 byte index length, 8 byte entry count, 2 byte version, CRC-32 of the preceding footer fields and the magic `SUBWAYST` |
+//!
+//! # Example
+//! ```rust
+//! use subway::skiplist::SkipList;
+//! use subway::sstable::{write_table, Table};
+//!
+//! let mut list = SkipList::new();
+//! for i in 0..1000u64 {
+//!     list.insert(i, format!("value-{}", i));
+//! }
+//! let path = std::env::temp_dir().join(format!("subway-doc-{}.sst", std::process::id()));
+//! write_table(&path, list.iter())?;
+//!
+//! let table: Table<u64, String> = Table::open(&path)?;
+//! assert_eq!(table.get(&42)?, Some("value-42".to_owned()));
+//! let keys = table.range(10..13).map(|entry| entry.map(|(key, _)| key)).collect::<Result<Vec<_>, _>>()?;
+//! assert_eq!(keys, vec![10, 11, 12]);
+//! # std::fs::remove_file(&path)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use crate::codec::{invalid_data, read_array, read_field, write_field, Codec};
+use crate::crc::crc32;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::vec;
+
+const MAGIC: &[u8; 8] = b"SUBWAYST";
+const VERSION: u16 = 1;
+const FOOTER_LEN: u64 = 38;
+/// Number of bytes after which a data block is closed.
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// Writes entries in key order as a table.
+///
+/// # Example
+/// ```rust
+/// use subway::sstable::TableWriter;
+///
+/// let mut writer = TableWriter::new(Vec::new());
+/// writer.add(&1u64, &"one".to_owned())?;
+/// writer.add(&2u64, &"two".to_owned())?;
+/// let bytes = writer.finish()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct TableWriter<K, V, W: Write> {
+    output: W,
+    // bytes written to `output` so far
+    offset: u64,
+    block_size: usize,
+    block: Vec<u8>,
+    block_first_key: Option<K>,
+    index: Vec<u8>,
+    last_key: Option<K>,
+    entries: u64,
+    marker: PhantomData<fn(&V)>,
+}
+
+impl<K, V, W> TableWriter<K, V, W>
+where
+    K: Ord + Clone + Codec,
+    V: Codec,
+    W: Write,
+{
+    /// Create a writer with blocks of [`DEFAULT_BLOCK_SIZE`] bytes.
+    pub fn new(output: W) -> TableWriter<K, V, W> {
+        TableWriter::with_block_size(output, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create a writer that closes a data block once it holds `block_size` bytes.
+    /// Smaller blocks make lookups read less at the cost of a larger index.
+    ///
+    /// # Panics
+    /// If `block_size` is zero.
+    pub fn with_block_size(output: W, block_size: usize) -> TableWriter<K, V, W> {
+        assert!(block_size > 0, "block_size must be at least 1");
+        TableWriter {
+            output,
+            offset: 0,
+            block_size,
+            block: Vec::with_capacity(block_size),
+            block_first_key: None,
+            index: Vec::new(),
+            last_key: None,
+            entries: 0,
+            marker: PhantomData,
+        }
+    }
+
+    /// Append an entry to the table.
+    ///
+    /// # Returns
+    /// An error of kind [`io::ErrorKind::InvalidInput`] if the key is smaller than
+    /// the key of the previous entry. Equal keys are kept in the order they are added.
+    pub fn add(&mut self, key: &K, value: &V) -> io::Result<()> {
+        if self.last_key.as_ref().is_some_and(|last| key < last) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "table entries must be added in key order",
+            ));
+        }
+        if self.block_first_key.is_none() {
+            self.block_first_key = Some(key.clone());
+        }
+        write_field(&mut self.block, key)?;
+        write_field(&mut self.block, value)?;
+        self.last_key = Some(key.clone());
+        self.entries += 1;
+        if self.block.len() >= self.block_size {
+            self.finish_block()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of entries added so far.
+    pub fn len(&self) -> u64 {
+        self.entries
+    }
+
+    /// Returns whether no entry was added yet.
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Returns the number of bytes the entries added so far take up in the table,
+    /// excluding the index and the footer.
+    pub fn size(&self) -> u64 {
+        self.offset + self.block.len() as u64
+    }
+
+    /// Write the index and the footer and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_block()?;
+        let index_offset = self.offset;
+        let index = std::mem::take(&mut self.index);
+        self.write_checksummed(&index)?;
+        let mut footer = Vec::with_capacity(FOOTER_LEN as usize);
+        footer.extend_from_slice(&index_offset.to_le_bytes());
+        footer.extend_from_slice(&(index.len() as u64).to_le_bytes());
+        footer.extend_from_slice(&self.entries.to_le_bytes());
+        footer.extend_from_slice(&VERSION.to_le_bytes());
+        footer.extend_from_slice(&crc32(&footer).to_le_bytes());
+        footer.extend_from_slice(MAGIC);
+        self.output.write_all(&footer)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+
+    fn finish_block(&mut self) -> io::Result<()> {
+        let first_key = match self.block_first_key.take() {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        let offset = self.offset;
+        let block = std::mem::take(&mut self.block);
+        self.write_checksummed(&block)?;
+        write_field(&mut self.index, &first_key)?;
+        self.index.extend_from_slice(&offset.to_le_bytes());
+        self.index
+            .extend_from_slice(&(block.len() as u32).to_le_bytes());
+        self.block = block;
+        self.block.clear();
+        Ok(())
+    }
+
+    fn write_checksummed(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)?;
+        self.output.write_all(&crc32(bytes).to_le_bytes())?;
+        self.offset += bytes.len() as u64 + 4;
+        Ok(())
+    }
+}
+
+/// Write entries sorted by key to a new table at `path` and sync it to disk.
+///
+/// # Returns
+/// An error of kind [`io::ErrorKind::InvalidInput`] if the entries are not sorted by key.
+pub fn write_table<P, K, V, I>(path: P, entries: I) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    K: Ord + Clone + Codec,
+    V: Codec,
+    I: IntoIterator<Item = (K, V)>,
+{
+    let mut writer = TableWriter::new(BufWriter::new(File::create(path)?));
+    for (key, value) in entries {
+        writer.add(&key, &value)?;
+    }
+    let file = writer
+        .finish()?
+        .into_inner()
+        .map_err(|error| error.into_error())?;
+    file.sync_all()
+}
+
+// Location of a data block along with the first key it holds.
+struct BlockHandle<K> {
+    first_key: K,
+    offset: u64,
+    len: u32,
+}
+
+/// Read-only table opened from a file written by [`TableWriter`] or [`write_table`].
+///
+/// Only the index is kept in memory, data blocks are read from the file on demand.
+/// A table can be shared between threads that read from it.
+pub struct Table<K, V> {
+    file: Mutex<File>,
+    index: Vec<BlockHandle<K>>,
+    entries: u64,
+    marker: PhantomData<fn() -> V>,
+}
+
+impl<K, V> Table<K, V>
+where
+    K: Ord + Clone + Codec,
+    V: Codec,
+{
+    /// Open the table at `path` and load its index.
+    ///
+    /// # Returns
+    /// An error of kind [`io::ErrorKind::InvalidData`] if the file is not a table or is corrupt.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Table<K, V>> {
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        if file_len < FOOTER_LEN {
+            return Err(invalid_data("file is too short to be a table"));
+        }
+        file.seek(SeekFrom::Start(file_len - FOOTER_LEN))?;
+        let footer: [u8; FOOTER_LEN as usize] = read_array(&mut file)?;
+        if &footer[30..] != MAGIC {
+            return Err(invalid_data("not a table"));
+        }
+        let mut fields = &footer[..];
+        let index_offset = u64::from_le_bytes(read_array(&mut fields)?);
+        let index_len = u64::from_le_bytes(read_array(&mut fields)?);
+        let entries = u64::from_le_bytes(read_array(&mut fields)?);
+        let version = u16::from_le_bytes(read_array(&mut fields)?);
+        let checksum = u32::from_le_bytes(read_array(&mut fields)?);
+        if checksum != crc32(&footer[..26]) {
+            return Err(invalid_data("table footer checksum mismatch"));
+        }
+        if version != VERSION {
+            return Err(invalid_data("unsupported table version"));
+        }
+        if index_offset.saturating_add(index_len).saturating_add(4) != file_len - FOOTER_LEN {
+            return Err(invalid_data("table index is out of bounds"));
+        }
+        let index_bytes = read_checksummed(&mut file, index_offset, index_len)?;
+        let mut index = Vec::new();
+        let mut handles = &index_bytes[..];
+        let mut key = Vec::new();
+        while !handles.is_empty() {
+            read_field(&mut handles, &mut key).map_err(malformed)?;
+            let offset = u64::from_le_bytes(read_array(&mut handles).map_err(malformed)?);
+            let len = u32::from_le_bytes(read_array(&mut handles).map_err(malformed)?);
+            if offset
+                .checked_add(len as u64 + 4)
+                .is_none_or(|end| end > index_offset)
+            {
+                return Err(invalid_data("table block is out of bounds"));
+            }
+            index.push(BlockHandle {
+                first_key: K::decode(&key)?,
+                offset,
+                len,
+            });
+        }
+        Ok(Table {
+            file: Mutex::new(file),
+            index,
+            entries,
+            marker: PhantomData,
+        })
+    }
+
+    /// Get the value associated with a key if it exists.
+    /// Reads at most the blocks that may hold the key.
+    ///
+    /// When the table holds the key more than once, the value written last is
+    /// returned, like [`SkipList::get`](crate::skiplist::SkipList::get) does for
+    /// the list the table was written from.
+    pub fn get(&self, key: &K) -> io::Result<Option<V>> {
+        let mut value = None;
+        for entry in self.range::<K, _>((Bound::Included(key), Bound::Included(key))) {
+            value = Some(entry?.1);
+        }
+        Ok(value)
+    }
+
+    /// Iterate over the entries whose keys fall in the given range, in key order.
+    /// The scan starts at the block that may hold the first key of the range.
+    pub fn range<'a, Q, R>(&'a self, range: R) -> Range<'a, K, V>
+    where
+        R: RangeBounds<Q>,
+        Q: core::borrow::Borrow<K> + 'a,
+    {
+        let start = clone_bound(range.start_bound());
+        let end = clone_bound(range.end_bound());
+        // equal keys may continue from the previous block,
+        // so start at the last block whose first key is smaller
+        let block = match &start {
+            Bound::Included(key) | Bound::Excluded(key) => self
+                .index
+                .partition_point(|handle| handle.first_key < *key)
+                .saturating_sub(1),
+            Bound::Unbounded => 0,
+        };
+        Range {
+            table: self,
+            block,
+            entries: Vec::new().into_iter(),
+            start,
+            end,
+            done: false,
+        }
+    }
+
+    /// Iterate over all entries in key order.
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range::<K, _>(..)
+    }
+
+    /// Returns the number of entries in the table.
+    pub fn len(&self) -> usize {
+        self.entries as usize
+    }
+
+    /// Returns whether table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Returns the smallest key in the table.
+    pub fn first_key(&self) -> Option<&K> {
+        self.index.first().map(|handle| &handle.first_key)
+    }
+
+    /// Returns the largest key in the table. Reads the last block.
+    pub fn last_key(&self) -> io::Result<Option<K>> {
+        let range = Range {
+            table: self,
+            block: self.index.len().saturating_sub(1),
+            entries: Vec::new().into_iter(),
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+            done: false,
+        };
+        let mut last = None;
+        for entry in range {
+            last = Some(entry?.0);
+        }
+        Ok(last)
+    }
+
+    fn read_block(&self, handle: &BlockHandle<K>) -> io::Result<Vec<u8>> {
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        read_checksummed(&mut file, handle.offset, handle.len as u64)
+    }
+}
+
+fn clone_bound<Q: core::borrow::Borrow<K>, K: Clone>(bound: Bound<&Q>) -> Bound<K> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key.borrow().clone()),
+        Bound::Excluded(key) => Bound::Excluded(key.borrow().clone()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+// Read `len` bytes at `offset` followed by their checksum.
+fn read_checksummed(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    Read::by_ref(file).take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(invalid_data("table is truncated"));
+    }
+    let checksum = u32::from_le_bytes(read_array(file)?);
+    if checksum != crc32(&bytes) {
+        return Err(invalid_data("table block checksum mismatch"));
+    }
+    Ok(bytes)
+}
+
+// Checksummed bytes that cannot be parsed were written incorrectly.
+fn malformed(error: io::Error) -> io::Error {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => invalid_data("table block is malformed"),
+        _ => error,
+    }
+}
+
+/// Iterator over the entries of a [`Table`] in key order, created by [`Table::range`].
+///
+/// Blocks are read from the file as the iterator reaches them.
+/// Iteration ends after the first error.
+pub struct Range<'a, K, V> {
+    table: &'a Table<K, V>,
+    // next block to read
+    block: usize,
+    // decoded entries left in the current block
+    entries: vec::IntoIter<(K, V)>,
+    start: Bound<K>,
+    end: Bound<K>,
+    done: bool,
+}
+
+impl<K, V> Range<'_, K, V>
+where
+    K: Ord + Clone + Codec,
+    V: Codec,
+{
+    fn load_block(&mut self) -> io::Result<bool> {
+        let handle = match self.table.index.get(self.block) {
+            Some(handle) => handle,
+            None => return Ok(false),
+        };
+        let bytes = self.table.read_block(handle)?;
+        self.block += 1;
+        let mut entries = Vec::new();
+        let mut input = &bytes[..];
+        let mut key = Vec::new();
+        let mut value = Vec::new();
+        while !input.is_empty() {
+            read_field(&mut input, &mut key).map_err(malformed)?;
+            read_field(&mut input, &mut value).map_err(malformed)?;
+            entries.push((K::decode(&key)?, V::decode(&value)?));
+        }
+        self.entries = entries.into_iter();
+        Ok(true)
+    }
+
+    fn before_start(&self, key: &K) -> bool {
+        match &self.start {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        }
+    }
+
+    fn after_end(&self, key: &K) -> bool {
+        match &self.end {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        }
+    }
+}
+
+impl<K, V> Iterator for Range<'_, K, V>
+where
+    K: Ord + Clone + Codec,
+    V: Codec,
+{
+    type Item = io::Result<(K, V)>;
+
+    fn next(&mut self) -> Option<io::Result<(K, V)>> {
+        while !self.done {
+            let (key, value) = match self.entries.next() {
+                Some(entry) => entry,
+                None => {
+                    match self.load_block() {
+                        Ok(true) => {}
+                        Ok(false) => self.done = true,
+                        Err(error) => {
+                            self.done = true;
+                            return Some(Err(error));
+                        }
+                    }
+                    continue;
+                }
+            };
+            if self.before_start(&key) {
+                continue;
+            }
+            if self.after_end(&key) {
+                self.done = true;
+                continue;
+            }
+            return Some(Ok((key, value)));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skiplist::SkipList;
+    use crate::test_util::temp_path;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Table path unique to the test that is removed when dropped.
+    struct TempTable(PathBuf);
+
+    impl TempTable {
+        fn new() -> TempTable {
+            TempTable(temp_path("sstable").with_extension("sst"))
+        }
+
+        // Write the entries with small blocks so that tables span many blocks.
+        fn write(&self, entries: &[(u64, String)], block_size: usize) {
+            let file = File::create(&self.0).unwrap();
+            let mut writer = TableWriter::with_block_size(file, block_size);
+            for (key, value) in entries {
+                writer.add(key, value).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+    }
+
+    impl Drop for TempTable {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn entries(keys: std::ops::Range<u64>) -> Vec<(u64, String)> {
+        keys.map(|key| (key * 2, format!("value-{}", key * 2)))
+            .collect()
+    }
+
+    fn keys<K: Ord + Clone + Codec, V: Codec>(range: Range<'_, K, V>) -> Vec<K> {
+        range.map(|entry| entry.unwrap().0).collect()
+    }
+
+    #[test]
+    fn test_write_skip_list_and_get() {
+        let mut list = SkipList::new();
+        for (key, value) in entries(0..2000) {
+            list.insert(key, value);
+        }
+        let path = TempTable::new();
+        write_table(&path.0, list.iter()).unwrap();
+        let table: Table<u64, String> = Table::open(&path.0).unwrap();
+        assert_eq!(table.len(), 2000);
+        assert!(table.index.len() > 1);
+        for key in 0..4000 {
+            let expected = if key % 2 == 0 {
+                Some(format!("value-{}", key))
+            } else {
+                None
+            };
+            assert_eq!(table.get(&key).unwrap(), expected);
+        }
+        assert_eq!(table.get(&5000).unwrap(), None);
+        assert_eq!(
+            table.iter().map(|entry| entry.unwrap()).collect::<Vec<_>>(),
+            list.collect()
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        let path = TempTable::new();
+        path.write(&entries(0..500), 64);
+        let table: Table<u64, String> = Table::open(&path.0).unwrap();
+        assert_eq!(keys(table.range(10..16)), vec![10, 12, 14]);
+        assert_eq!(keys(table.range(11..=16)), vec![12, 14, 16]);
+        assert_eq!(
+            keys(table.range((Bound::Excluded(10), Bound::Excluded(16)))),
+            vec![12, 14]
+        );
+        assert_eq!(keys(table.range(..4)), vec![0, 2]);
+        assert_eq!(keys(table.range(994..)), vec![994, 996, 998]);
+        assert_eq!(keys(table.range(2000..)), Vec::<u64>::new());
+        assert_eq!(keys(table.range(5..5)), Vec::<u64>::new());
+        assert_eq!(keys(table.iter()).len(), 500);
+        assert_eq!(table.first_key(), Some(&0));
+        assert_eq!(table.last_key().unwrap(), Some(998));
+    }
+
+    #[test]
+    fn test_equal_keys_across_blocks() {
+        let mut entries = vec![(1, "a".to_owned())];
+        for i in 0..50 {
+            entries.push((7, format!("seven-{}", i)));
+        }
+        entries.push((9, "b".to_owned()));
+        let path = TempTable::new();
+        path.write(&entries, 32);
+        let table: Table<u64, String> = Table::open(&path.0).unwrap();
+        assert_eq!(table.get(&7).unwrap(), Some("seven-49".to_owned()));
+        let sevens: Vec<String> = table.range(7..=7).map(|entry| entry.unwrap().1).collect();
+        assert_eq!(sevens.len(), 50);
+        assert_eq!(sevens[49], "seven-49");
+    }
+
+    #[test]
+    fn test_empty_table() {
+        let path = TempTable::new();
+        path.write(&[], DEFAULT_BLOCK_SIZE);
+        let table: Table<u64, String> = Table::open(&path.0).unwrap();
+        assert!(table.is_empty());
+        assert_eq!(table.get(&1).unwrap(), None);
+        assert_eq!(table.iter().count(), 0);
+        assert_eq!(table.first_key(), None);
+        assert_eq!(table.last_key().unwrap(), None);
+    }
+
+    #[test]
+    fn test_rejects_unsorted_entries() {
+        let mut writer = TableWriter::new(Vec::new());
+        writer.add(&2u64, &0u64).unwrap();
+        writer.add(&2u64, &1u64).unwrap();
+        assert_eq!(writer.len(), 2);
+        assert!(writer.size() > 0);
+        let error = writer.add(&1u64, &0u64).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        let path = TempTable::new();
+        path.write(&entries(0..500), 256);
+        let bytes = fs::read(&path.0).unwrap();
+        // a corrupt data block fails the reads that touch it
+        let mut corrupt = bytes.clone();
+        corrupt[10] ^= 0xff;
+        fs::write(&path.0, &corrupt).unwrap();
+        let table: Table<u64, String> = Table::open(&path.0).unwrap();
+        let error = table.get(&0).expect_err("corruption was not detected");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(table.get(&998).unwrap(), Some("value-998".to_owned()));
+        let results: Vec<io::Result<(u64, String)>> = table.iter().collect();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+        // a corrupt footer or a truncated file cannot be opened
+        for length in [bytes.len() - 1, bytes.len() / 2, 10] {
+            fs::write(&path.0, &bytes[..length]).unwrap();
+            let result = Table::<u64, String>::open(&path.0);
+            let error = result.err().expect("truncation was not detected");
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        let mut corrupt = bytes.clone();
+        let footer = bytes.len() - FOOTER_LEN as usize;
+        corrupt[footer + 16] ^= 0x01;
+        fs::write(&path.0, &corrupt).unwrap();
+        assert!(Table::<u64, String>::open(&path.0).is_err());
+    }
+
+    #[test]
+    fn test_rejects_block_offset_past_the_end() {
+        let path = TempTable::new();
+        path.write(&entries(0..500), 256);
+        let mut bytes = fs::read(&path.0).unwrap();
+        let footer = bytes.len() - FOOTER_LEN as usize;
+        let mut fields = &bytes[footer..];
+        let index_offset = u64::from_le_bytes(read_array(&mut fields).unwrap()) as usize;
+        let index_len = u64::from_le_bytes(read_array(&mut fields).unwrap()) as usize;
+        // the offset of the first block follows its 8 byte key and the key length,
+        // an offset that overflows still passes the recomputed index checksum
+        let offset = index_offset + 12;
+        bytes[offset..offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let checksum = crc32(&bytes[index_offset..index_offset + index_len]);
+        let end = index_offset + index_len;
+        bytes[end..end + 4].copy_from_slice(&checksum.to_le_bytes());
+        fs::write(&path.0, &bytes).unwrap();
+        let result = Table::<u64, String>::open(&path.0);
+        let error = result.err().expect("block offset was accepted");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,19 @@
+// Helpers shared by the unit tests of several modules.
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "std")]
+static NEXT_PATH: AtomicUsize = AtomicUsize::new(0);
+
+// Compiles only if `T` can be sent to and shared between threads.
+pub(crate) fn assert_send_sync<T: Send + Sync>() {}
+
+// Path in the temporary directory that no other test of this process uses,
+// named after `name`. Tests wrap it in a type that removes what they created.
+#[cfg(feature = "std")]
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    let id = NEXT_PATH.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("subway-{}-{}-{}", name, std::process::id(), id))
+}
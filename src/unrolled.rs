@@ -0,0 +1,553 @@
+use crate::rng::{random_seed, tower_height, SplitMix64};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use rand::RngCore;
+
+// Marks the end of a level.
+const NIL: usize = usize::MAX;
+// Slot of the header block which precedes every block and spans every level.
+const HEAD: usize = 0;
+/// Number of entries a block holds before it is split.
+const DEFAULT_BLOCK_SIZE: usize = 32;
+/// Probability with which a block is promoted to the next level.
+const PROBABILITY: f64 = 0.5;
+/// Upper bound on the number of levels.
+const MAX_LEVEL: usize = 32;
+
+struct Block<K, V> {
+    // sorted entries, empty for the header and for free slots
+    entries: Vec<(K, V)>,
+    // successor of the block in each level it spans
+    next: Vec<usize>,
+}
+
+/// Unrolled skip list that stores a small sorted block of entries in every
+/// node of the bottom level.
+///
+/// Only the first key of each block takes part in the upper levels, so the
+/// list needs far fewer nodes than a [`SkipList`](../skiplist/struct.SkipList.html)
+/// and scans walk over contiguous memory.
+/// Blocks are split when they fill up and merged with their neighbour when
+/// they fall below a quarter of their capacity.
+/// This suits small keys such as integers where scans dominate.
+///
+/// # Example
+/// ```rust
+/// use subway::unrolled::UnrolledSkipList;
+///
+/// let mut series = UnrolledSkipList::new();
+/// for timestamp in 0..100u64 {
+///     series.insert(timestamp, timestamp * 2);
+/// }
+/// assert_eq!(series.get(&10), Some(20));
+/// let total: u64 = series.iter().map(|(_, value)| value).sum();
+/// assert_eq!(total, 9900);
+/// ```
+pub struct UnrolledSkipList<K, V> {
+    size: usize,
+    block_size: usize,
+    blocks: Vec<Block<K, V>>,
+    // slots of removed blocks available for reuse
+    free: Vec<usize>,
+    rng: SplitMix64,
+}
+
+impl<K, V> UnrolledSkipList<K, V>
+where
+    K: Ord,
+{
+    /// Create an empty list whose blocks hold up to `32` entries.
+    pub fn new() -> UnrolledSkipList<K, V> {
+        UnrolledSkipList::with_block_size(DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create an empty list whose blocks hold up to `block_size` entries.
+    ///
+    /// # Panics
+    /// If `block_size` is less than `4`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::unrolled::UnrolledSkipList;
+    ///
+    /// let list: UnrolledSkipList<u64, u64> = UnrolledSkipList::with_block_size(128);
+    /// assert_eq!(list.block_size(), 128);
+    /// ```
+    pub fn with_block_size(block_size: usize) -> UnrolledSkipList<K, V> {
+        assert!(block_size >= 4, "block_size must be at least 4");
+        UnrolledSkipList {
+            size: 0,
+            block_size,
+            blocks: vec![Block {
+                entries: Vec::new(),
+                next: Vec::new(),
+            }],
+            free: Vec::new(),
+            rng: SplitMix64::new(random_seed()),
+        }
+    }
+
+    /// Insert the given key and value into the list.
+    /// Entries with equal keys are kept in insertion order.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::unrolled::UnrolledSkipList;
+    ///
+    /// let mut list = UnrolledSkipList::new();
+    /// list.insert(2, "two");
+    /// list.insert(1, "one");
+    /// assert_eq!(list.collect(), vec![(1, "one"), (2, "two")]);
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.height() == 0 {
+            let block = self.allocate(Vec::with_capacity(self.block_size), 1);
+            self.blocks[HEAD].next.push(NIL);
+            self.blocks[block].next[0] = NIL;
+            self.blocks[HEAD].next[0] = block;
+        }
+        let path = self.search_path(&key, NIL);
+        let block = self.containing_block(path[0]);
+        let entries = &mut self.blocks[block].entries;
+        let position = entries.partition_point(|(entry_key, _)| entry_key <= &key);
+        entries.insert(position, (key, value));
+        self.size += 1;
+        if self.blocks[block].entries.len() >= self.block_size {
+            self.split(block, &path);
+        }
+    }
+
+    /// Get the value associated with a key if it exists.
+    /// If the key was inserted more than once the most recent value is returned.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::unrolled::UnrolledSkipList;
+    ///
+    /// let mut list = UnrolledSkipList::new();
+    /// list.insert(20, "Strawberry Topping");
+    /// assert_eq!(list.get(&20), Some("Strawberry Topping"));
+    /// assert_eq!(list.get(&40), None);
+    /// ```
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let (block, position) = self.find(key)?;
+        Some(self.blocks[block].entries[position].1.clone())
+    }
+
+    /// Delete the value associated with the key.
+    /// If the key was inserted more than once the most recent entry is removed.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::unrolled::UnrolledSkipList;
+    ///
+    /// let mut list = UnrolledSkipList::new();
+    /// list.insert(20, "Strawberry Topping");
+    /// list.insert(100, "Lemon Cream");
+    /// list.delete(&100);
+    /// assert_eq!(list.len(), 1);
+    /// ```
+    pub fn delete(&mut self, key: &K) {
+        let (block, position) = match self.find(key) {
+            Some(found) => found,
+            None => return,
+        };
+        self.blocks[block].entries.remove(position);
+        self.size -= 1;
+        if self.blocks[block].entries.len() < self.block_size / 4 {
+            let path = self.search_path(key, block);
+            self.refill(block, &path);
+        }
+    }
+
+    /// Find the largest key after which the supplied key can be inserted.
+    ///
+    /// # Returns
+    /// `Option` of key after which the given key can be inserted.
+    /// `None` implies no key less that supplied key exists.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::unrolled::UnrolledSkipList;
+    ///
+    /// let mut list = UnrolledSkipList::new();
+    /// list.insert(1, "small");
+    /// list.insert(4, "x-large");
+    /// list.insert(3, "large");
+    /// assert_eq!(list.bisect(&2), Some(1));
+    /// assert_eq!(list.bisect(&0), None);
+    /// ```
+    pub fn bisect(&self, key: &K) -> Option<K>
+    where
+        K: Clone,
+    {
+        let block = self.search_path(key, NIL)[0];
+        let entries = &self.blocks[block].entries;
+        let position = entries.partition_point(|(entry_key, _)| entry_key <= key);
+        position
+            .checked_sub(1)
+            .map(|position| entries[position].0.clone())
+    }
+
+    /// Iterate over the entries in key order.
+    ///
+    /// # Example
+    /// ```rust
+    /// use subway::unrolled::UnrolledSkipList;
+    ///
+    /// let mut list = UnrolledSkipList::new();
+    /// list.insert(2, 20);
+    /// list.insert(1, 10);
+    /// let keys: Vec<&i32> = list.iter().map(|(key, _)| key).collect();
+    /// assert_eq!(keys, vec![&1, &2]);
+    /// ```
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            list: self,
+            block: self.next(HEAD, 0),
+            position: 0,
+        }
+    }
+
+    /// Collect the entries sorted by key into a collection.
+    pub fn collect(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Returns the size of the list.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Returns whether list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns the maximum number of entries held by a block.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Remove all entries from the list.
+    pub fn clear(&mut self) {
+        self.blocks.truncate(1);
+        self.blocks[HEAD].next.clear();
+        self.free.clear();
+        self.size = 0;
+    }
+
+    fn height(&self) -> usize {
+        self.blocks[HEAD].next.len()
+    }
+
+    fn next(&self, block: usize, level: usize) -> usize {
+        if block == NIL {
+            return NIL;
+        }
+        self.blocks[block].next.get(level).copied().unwrap_or(NIL)
+    }
+
+    // Whether the first key of the block is not greater than the supplied key.
+    fn precedes(&self, block: usize, key: &K) -> bool {
+        match self
+            .blocks
+            .get(block)
+            .and_then(|block| block.entries.first())
+        {
+            Some((first_key, _)) => first_key.cmp(key) != Ordering::Greater,
+            None => false,
+        }
+    }
+
+    // The last block in every level whose first key is not greater than the
+    // supplied key, stopping before `stop` so that the path to an existing
+    // block lists its predecessors.
+    fn search_path(&self, key: &K, stop: usize) -> Vec<usize> {
+        let height = self.height();
+        let mut path = vec![HEAD; height.max(1)];
+        let mut block = HEAD;
+        for level in (0..height).rev() {
+            loop {
+                let next = self.next(block, level);
+                if next == stop || !self.precedes(next, key) {
+                    break;
+                }
+                block = next;
+            }
+            path[level] = block;
+        }
+        path
+    }
+
+    // Keys smaller than every first key belong to the first block.
+    fn containing_block(&self, block: usize) -> usize {
+        if block == HEAD {
+            return self.next(HEAD, 0);
+        }
+        block
+    }
+
+    // Block and position of the last entry holding the key.
+    fn find(&self, key: &K) -> Option<(usize, usize)> {
+        let block = self.search_path(key, NIL)[0];
+        let entries = &self.blocks[block].entries;
+        let position = entries
+            .partition_point(|(entry_key, _)| entry_key <= key)
+            .checked_sub(1)?;
+        if &entries[position].0 == key {
+            return Some((block, position));
+        }
+        None
+    }
+
+    // Move the upper half of a full block into a new block linked after it.
+    fn split(&mut self, block: usize, path: &[usize]) {
+        let half = self.blocks[block].entries.len() / 2;
+        let mut entries = Vec::with_capacity(self.block_size);
+        entries.extend(self.blocks[block].entries.drain(half..));
+        let height = tower_height(self.rng.next_u64(), PROBABILITY, MAX_LEVEL);
+        let new_block = self.allocate(entries, height);
+        for level in 0..height {
+            if level == self.height() {
+                self.blocks[HEAD].next.push(NIL);
+            }
+            let pred = self.predecessor(block, path, level);
+            self.blocks[new_block].next[level] = self.next(pred, level);
+            self.blocks[pred].next[level] = new_block;
+        }
+    }
+
+    // Merge a block that fell below a quarter of its capacity with the block
+    // after it, or borrow entries from that block if both are too large to merge.
+    // The path holds the predecessors of the block in every level.
+    fn refill(&mut self, block: usize, path: &[usize]) {
+        let next = self.next(block, 0);
+        if next == NIL {
+            if self.blocks[block].entries.is_empty() {
+                self.unlink(block, path);
+            }
+            return;
+        }
+        let combined = self.blocks[block].entries.len() + self.blocks[next].entries.len();
+        if combined < self.block_size {
+            let mut entries = core::mem::take(&mut self.blocks[next].entries);
+            self.blocks[block].entries.append(&mut entries);
+            let next_path: Vec<usize> = (0..self.height())
+                .map(|level| self.predecessor(block, path, level))
+                .collect();
+            self.unlink(next, &next_path);
+        } else {
+            let borrowed = (self.blocks[next].entries.len() - self.blocks[block].entries.len()) / 2;
+            let mut entries: Vec<(K, V)> = self.blocks[next].entries.drain(..borrowed).collect();
+            self.blocks[block].entries.append(&mut entries);
+        }
+    }
+
+    // Last block at or before `block` in the given level.
+    fn predecessor(&self, block: usize, path: &[usize], level: usize) -> usize {
+        if self.blocks[block].next.len() > level {
+            return block;
+        }
+        path.get(level).copied().unwrap_or(HEAD)
+    }
+
+    fn unlink(&mut self, block: usize, path: &[usize]) {
+        for level in 0..self.blocks[block].next.len() {
+            let pred = path.get(level).copied().unwrap_or(HEAD);
+            self.blocks[pred].next[level] = self.blocks[block].next[level];
+        }
+        self.release(block);
+        while self.height() > 0 && self.next(HEAD, self.height() - 1) == NIL {
+            self.blocks[HEAD].next.pop();
+        }
+    }
+
+    fn allocate(&mut self, entries: Vec<(K, V)>, height: usize) -> usize {
+        let block = Block {
+            entries,
+            next: vec![NIL; height],
+        };
+        match self.free.pop() {
+            Some(slot) => {
+                self.blocks[slot] = block;
+                slot
+            }
+            None => {
+                self.blocks.push(block);
+                self.blocks.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, slot: usize) {
+        self.blocks[slot] = Block {
+            entries: Vec::new(),
+            next: Vec::new(),
+        };
+        self.free.push(slot);
+    }
+}
+
+impl<K, V> Default for UnrolledSkipList<K, V>
+where
+    K: Ord,
+{
+    fn default() -> UnrolledSkipList<K, V> {
+        UnrolledSkipList::new()
+    }
+}
+
+/// Iterator over the entries of an [`UnrolledSkipList`] in key order.
+pub struct Iter<'a, K, V> {
+    list: &'a UnrolledSkipList<K, V>,
+    block: usize,
+    position: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.block != NIL {
+            let block = &self.list.blocks[self.block];
+            if let Some((key, value)) = block.entries.get(self.position) {
+                self.position += 1;
+                return Some((key, value));
+            }
+            self.block = block.next[0];
+            self.position = 0;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Check ordering, block fill and the links of every level.
+    fn assert_valid(list: &UnrolledSkipList<i32, usize>) {
+        let entries = list.collect();
+        assert_eq!(entries.len(), list.len());
+        assert!(entries.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        let mut block = list.next(HEAD, 0);
+        while block != NIL {
+            let size = list.blocks[block].entries.len();
+            assert!(size > 0 && size < list.block_size);
+            block = list.next(block, 0);
+        }
+        for level in 0..list.height() {
+            let mut block = list.next(HEAD, level);
+            assert_ne!(block, NIL);
+            while block != NIL {
+                assert!(list.blocks[block].next.len() > level);
+                let next = list.next(block, level);
+                if next != NIL {
+                    assert!(list.blocks[block].entries[0].0 <= list.blocks[next].entries[0].0);
+                }
+                block = next;
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_splits_blocks() {
+        let mut list = UnrolledSkipList::with_block_size(8);
+        for i in 0..1000 {
+            list.insert(i, i as usize);
+        }
+        assert_valid(&list);
+        assert_eq!(list.len(), 1000);
+        assert!(list.blocks.len() > 1000 / 8);
+        assert_eq!(list.get(&999), Some(999));
+        assert_eq!(list.bisect(&2000), Some(999));
+    }
+
+    #[test]
+    fn test_insert_before_first_key() {
+        let mut list = UnrolledSkipList::with_block_size(4);
+        for i in (0..100).rev() {
+            list.insert(i, i as usize);
+            assert_valid(&list);
+        }
+        assert_eq!(
+            list.collect(),
+            (0..100).map(|i| (i, i as usize)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_delete_merges_blocks() {
+        let mut list = UnrolledSkipList::with_block_size(8);
+        for i in 0..1000 {
+            list.insert(i, i as usize);
+        }
+        for i in 0..1000 {
+            list.delete(&i);
+            assert_valid(&list);
+            assert_eq!(list.get(&i), None);
+        }
+        assert!(list.is_empty());
+        assert_eq!(list.height(), 0);
+        list.insert(7, 7);
+        assert_eq!(list.collect(), vec![(7, 7)]);
+    }
+
+    #[test]
+    fn test_matches_model() {
+        let mut rng = SplitMix64::new(99);
+        let mut list = UnrolledSkipList::with_block_size(6);
+        let mut model: Vec<(i32, usize)> = Vec::new();
+        for step in 0..5000 {
+            let key = (rng.next_u64() % 300) as i32;
+            if rng.next_u64() % 5 < 2 {
+                list.delete(&key);
+                if let Some(position) = model.iter().rposition(|entry| entry.0 == key) {
+                    model.remove(position);
+                }
+            } else {
+                list.insert(key, step);
+                let position = model.iter().position(|entry| entry.0 > key);
+                model.insert(position.unwrap_or(model.len()), (key, step));
+            }
+            assert_valid(&list);
+            let expected = model
+                .iter()
+                .rev()
+                .find(|entry| entry.0 == key)
+                .map(|entry| entry.1);
+            assert_eq!(list.get(&key), expected);
+        }
+        assert_eq!(list.collect(), model);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut list = UnrolledSkipList::new();
+        for i in 0..100 {
+            list.insert(i, i as usize);
+        }
+        list.clear();
+        assert!(list.is_empty());
+        assert_eq!(list.iter().count(), 0);
+        list.insert(1, 1);
+        assert_eq!(list.get(&1), Some(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rejects_small_blocks() {
+        UnrolledSkipList::<i32, i32>::with_block_size(2);
+    }
+}